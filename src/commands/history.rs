@@ -0,0 +1,78 @@
+use crate::metadata::ledger::read_ledger;
+use crate::pipeline::report::print_table;
+use crate::UploadError;
+use chrono::{DateTime, Local, Utc};
+use clap::ArgMatches;
+use log::info;
+
+// List the most recent publications and failures across every photo
+pub(crate) fn run(matches: &ArgMatches) -> Result<(), UploadError> {
+	let limit = matches
+		.value_of("LIMIT")
+		.unwrap()
+		.parse::<usize>()
+		.map_err(|_| UploadError::UnknownError(Some("The limit must be a number".into())))?;
+	let ledger = read_ledger()?;
+	let mut rows: Vec<(DateTime<Utc>, Vec<String>, bool)> = Vec::new();
+
+	for entry in ledger.photos.values() {
+		let photo = if entry.title.is_empty() {
+			entry.path.clone()
+		} else {
+			entry.title.clone()
+		};
+
+		for (destination, publication) in &entry.publications {
+			rows.push((
+				publication.published_at,
+				vec![
+					format_date(&publication.published_at),
+					photo.clone(),
+					destination.clone(),
+					"published".into(),
+					publication.id.clone(),
+				],
+				false,
+			));
+		}
+
+		for (destination, failure) in &entry.failures {
+			rows.push((
+				failure.failed_at,
+				vec![
+					format_date(&failure.failed_at),
+					photo.clone(),
+					destination.clone(),
+					"failed".into(),
+					failure.error.clone(),
+				],
+				true,
+			));
+		}
+	}
+
+	if rows.is_empty() {
+		info!("Nothing has been published yet");
+
+		return Ok(());
+	}
+
+	rows.sort_by_key(|row| std::cmp::Reverse(row.0));
+
+	let rows: Vec<(Vec<String>, bool)> = rows
+		.into_iter()
+		.take(limit)
+		.map(|(_, columns, failed)| (columns, failed))
+		.collect();
+
+	print_table(&["DATE", "PHOTO", "DESTINATION", "RESULT", "DETAIL"], &rows);
+
+	Ok(())
+}
+
+pub(crate) fn format_date(date: &DateTime<Utc>) -> String {
+	date
+		.with_timezone(&Local)
+		.format("%Y-%m-%d %H:%M")
+		.to_string()
+}
@@ -0,0 +1,5 @@
+pub(crate) mod history;
+pub(crate) mod publish;
+pub(crate) mod status;
+pub(crate) mod unpublish;
+pub(crate) mod update;
@@ -0,0 +1,61 @@
+use crate::metadata::config::read_config;
+use crate::metadata::edits::MetadataEdits;
+use crate::metadata::exif::MetadataReader;
+use crate::metadata::ledger::read_ledger;
+use crate::pipeline::paths::collect_photos;
+use crate::pipeline::publish::{preview_photo, publish_photo, PublishOptions};
+use crate::pipeline::report::{print_report, PhotoResult};
+use crate::publishers::flickr::Flickr;
+use crate::UploadError;
+use clap::ArgMatches;
+
+pub(crate) fn run(matches: &ArgMatches) -> Result<(), UploadError> {
+	let mut config = read_config()?;
+	let photos = collect_photos(
+		matches.values_of("PATH").unwrap(),
+		matches.is_present("RECURSIVE"),
+	)?;
+
+	if photos.is_empty() {
+		return Err(UploadError::UnknownError(Some(
+			"No photos were found to upload".into(),
+		)));
+	}
+
+	let dry_run = matches.is_present("DRY_RUN");
+	let options = PublishOptions {
+		keep_going: matches.is_present("KEEP_GOING"),
+		force: matches.is_present("FORCE"),
+		edits: MetadataEdits::from_matches(matches)?,
+		flickr_error: if dry_run {
+			None
+		} else {
+			Flickr::authenticate_batch(&mut config.flickr)
+		},
+	};
+	let reader = MetadataReader::load(&config)?;
+	let mut ledger = read_ledger()?;
+
+	let results: Vec<PhotoResult> = photos
+		.into_iter()
+		.map(|path| {
+			let result = if dry_run {
+				preview_photo(&config, &reader, &ledger, &path, &options)
+			} else {
+				publish_photo(&config, &reader, &mut ledger, &path, &options)
+			};
+
+			PhotoResult { path, result }
+		})
+		.collect();
+	let failures = print_report(&results, if dry_run { "previewed" } else { "published" });
+
+	if failures > 0 {
+		return Err(UploadError::UnknownError(Some(format!(
+			"{} upload(s) failed. See the summary above for details",
+			failures
+		))));
+	}
+
+	Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::commands::history::format_date;
+use crate::metadata::config::read_config;
+use crate::metadata::ledger::{content_hash, read_ledger};
+use crate::pipeline::report::print_table;
+use crate::UploadError;
+use clap::ArgMatches;
+use log::{info, warn};
+
+// Show which destinations have a photo, which failed, and which it hasn't
+// been published to yet
+pub(crate) fn run(matches: &ArgMatches) -> Result<(), UploadError> {
+	let path = matches.value_of("PATH").unwrap();
+	let hash = content_hash(path)?;
+	let ledger = read_ledger()?;
+	let mut destinations: Vec<String> = read_config()
+		.map(|config| config.destinations())
+		.unwrap_or_default();
+
+	let entry = match ledger.find(&hash, path) {
+		Some((published_hash, entry)) => {
+			if published_hash != &hash {
+				warn!("{} has changed since it was last published", path);
+			}
+
+			entry
+		}
+		None => {
+			info!("{} hasn't been published anywhere yet", path);
+
+			return Ok(());
+		}
+	};
+
+	for destination in entry.publications.keys().chain(entry.failures.keys()) {
+		if !destinations.contains(destination) {
+			destinations.push(destination.clone());
+		}
+	}
+
+	info!("{} ({})", entry.title, entry.path);
+
+	let mut rows: Vec<(Vec<String>, bool)> = Vec::new();
+
+	for destination in destinations {
+		let publication = entry.publications.get(&destination);
+		let failure = entry.failures.get(&destination);
+
+		if let Some(publication) = publication {
+			rows.push((
+				vec![
+					destination.clone(),
+					"published".into(),
+					format_date(&publication.published_at),
+					publication.id.clone(),
+				],
+				false,
+			));
+		}
+
+		if let Some(failure) = failure {
+			rows.push((
+				vec![
+					destination.clone(),
+					"failed".into(),
+					format_date(&failure.failed_at),
+					failure.error.clone(),
+				],
+				true,
+			));
+		}
+
+		if publication.is_none() && failure.is_none() {
+			rows.push((
+				vec![destination, "not published".into(), "-".into(), "".into()],
+				false,
+			));
+		}
+	}
+
+	print_table(&["DESTINATION", "RESULT", "DATE", "DETAIL"], &rows);
+
+	Ok(())
+}
@@ -0,0 +1,124 @@
+use crate::metadata::config::read_config;
+use crate::metadata::ledger::{content_hash, read_ledger, write_ledger, Ledger, Publication};
+use crate::pipeline::report::print_table;
+use crate::publishers::cloudinary::Cloudinary;
+use crate::publishers::flickr::Flickr;
+use crate::publishers::script::Script;
+use crate::{PhotoDestination, UploadError};
+use clap::ArgMatches;
+use log::{error, info, warn};
+use std::collections::BTreeMap;
+
+// Take the photo down from a destination if the ledger says it's there, and
+// forget about it once it's gone
+fn remove_from<D: PhotoDestination>(
+	config: &D::Config,
+	hash: &str,
+	publications: &mut BTreeMap<String, Publication>,
+	ledger: &mut Ledger,
+) -> Option<(String, Result<String, UploadError>)> {
+	let destination = D::destination(config);
+	let publication = publications.remove(&destination)?;
+	let result = D::remove(config, &publication.id);
+
+	match &result {
+		Ok(_) => {
+			ledger.remove(hash, &destination);
+
+			if let Err(err) = write_ledger(ledger) {
+				warn!("Couldn't remove {} from the ledger: {}", destination, err);
+			}
+		}
+		Err(err) => error!("Removing the photo from {} failed: {}", destination, err),
+	}
+
+	Some((destination, result.map(|_| publication.id)))
+}
+
+// Remove a photo from every destination the ledger says it was published to
+pub(crate) fn run(matches: &ArgMatches) -> Result<(), UploadError> {
+	let path = matches.value_of("PATH").unwrap();
+	let mut config = read_config()?;
+	let mut ledger = read_ledger()?;
+	let (hash, mut publications) = match ledger.find(&content_hash(path)?, path) {
+		Some((hash, entry)) if !entry.publications.is_empty() => {
+			(hash.clone(), entry.publications.clone())
+		}
+		_ => {
+			info!("{} hasn't been published anywhere", path);
+
+			return Ok(());
+		}
+	};
+
+	if let Some(flickr_config) = config.flickr.take() {
+		config.flickr = if publications.contains_key(&Flickr::destination(&flickr_config)) {
+			Some(Flickr::authenticate(flickr_config)?)
+		} else {
+			Some(flickr_config)
+		};
+	}
+
+	let mut results: Vec<(String, Result<String, UploadError>)> = Vec::new();
+
+	if let Some(cloudinary_config) = &config.cloudinary {
+		results.extend(remove_from::<Cloudinary>(
+			cloudinary_config,
+			&hash,
+			&mut publications,
+			&mut ledger,
+		));
+	}
+
+	if let Some(scripts) = &config.script {
+		for script in scripts {
+			results.extend(remove_from::<Script>(
+				script,
+				&hash,
+				&mut publications,
+				&mut ledger,
+			));
+		}
+	}
+
+	if let Some(flickr_config) = &config.flickr {
+		results.extend(remove_from::<Flickr>(
+			flickr_config,
+			&hash,
+			&mut publications,
+			&mut ledger,
+		));
+	}
+
+	// Whatever is left was published to a destination that has since been
+	// removed from the config, so there's no way to reach it
+	for destination in publications.keys() {
+		results.push((
+			destination.clone(),
+			Err(UploadError::MissingConfig(Some(format!(
+				"{} isn't configured anymore",
+				destination
+			)))),
+		));
+	}
+
+	let failures = results.iter().filter(|(_, result)| result.is_err()).count();
+	let rows: Vec<(Vec<String>, bool)> = results
+		.into_iter()
+		.map(|(destination, result)| match result {
+			Ok(id) => (vec![destination, "removed".into(), id], false),
+			Err(err) => (vec![destination, "failed".into(), err.to_string()], true),
+		})
+		.collect();
+
+	print_table(&["DESTINATION", "RESULT", "DETAIL"], &rows);
+
+	if failures > 0 {
+		return Err(UploadError::UnknownError(Some(format!(
+			"{} destination(s) still have the photo. See the summary above for details",
+			failures
+		))));
+	}
+
+	Ok(())
+}
@@ -0,0 +1,57 @@
+use crate::metadata::config::read_config;
+use crate::metadata::edits::MetadataEdits;
+use crate::metadata::exif::MetadataReader;
+use crate::metadata::ledger::read_ledger;
+use crate::pipeline::paths::collect_photos;
+use crate::pipeline::report::{print_report, PhotoResult};
+use crate::pipeline::update::update_photo;
+use crate::publishers::flickr::Flickr;
+use crate::UploadError;
+use clap::ArgMatches;
+
+pub(crate) fn run(matches: &ArgMatches) -> Result<(), UploadError> {
+	let mut config = read_config()?;
+	let photos = collect_photos(
+		matches.values_of("PATH").unwrap(),
+		matches.is_present("RECURSIVE"),
+	)?;
+
+	if photos.is_empty() {
+		return Err(UploadError::UnknownError(Some(
+			"No photos were found to update".into(),
+		)));
+	}
+
+	let keep_going = matches.is_present("KEEP_GOING");
+	let edits = MetadataEdits::from_matches(matches)?;
+	let reader = MetadataReader::load(&config)?;
+	let mut ledger = read_ledger()?;
+
+	let flickr_error = Flickr::authenticate_batch(&mut config.flickr);
+	let results: Vec<PhotoResult> = photos
+		.into_iter()
+		.map(|path| {
+			let result = update_photo(
+				&config,
+				&reader,
+				&mut ledger,
+				&path,
+				keep_going,
+				&edits,
+				flickr_error.as_ref(),
+			);
+
+			PhotoResult { path, result }
+		})
+		.collect();
+	let failures = print_report(&results, "updated");
+
+	if failures > 0 {
+		return Err(UploadError::UnknownError(Some(format!(
+			"{} update(s) failed. See the summary above for details",
+			failures
+		))));
+	}
+
+	Ok(())
+}
@@ -1,54 +1,219 @@
+mod commands;
 mod metadata;
+mod pipeline;
+mod processing;
 mod publishers;
 
-use crate::metadata::config::read_config;
-use crate::metadata::exif::{get_metadata, Metadata};
-use crate::publishers::cloudinary::Cloudinary;
-use crate::publishers::flickr::Flickr;
-use crate::publishers::script::Script;
-use clap::{App, Arg, ArgMatches};
+use crate::metadata::exif::Metadata;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use metadata::config::PublisherConfig;
+use serde_json::Value;
 use simplelog::{LevelFilter, TermLogger};
-use log::debug;
+use std::fmt;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) enum UploadError {
 	BadGateway(Option<String>),
 	MalformedConfig(Option<String>),
+	MalformedMetadata(Option<String>),
 	MissingConfig(Option<String>),
+	MissingMetadata(Option<String>),
 	UnknownError(Option<String>),
 }
 
+impl fmt::Display for UploadError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let (kind, message) = match self {
+			UploadError::BadGateway(message) => ("Bad gateway", message),
+			UploadError::MalformedConfig(message) => ("Malformed config", message),
+			UploadError::MalformedMetadata(message) => ("Malformed metadata", message),
+			UploadError::MissingConfig(message) => ("Missing config", message),
+			UploadError::MissingMetadata(message) => ("Missing metadata", message),
+			UploadError::UnknownError(message) => ("Unknown error", message),
+		};
+
+		match message {
+			Some(message) => write!(f, "{}: {}", kind, message),
+			None => write!(f, "{}", kind),
+		}
+	}
+}
+
 pub(crate) trait PhotoDestination {
 	type Config: PublisherConfig;
 
-	fn upload(config: Self::Config, photo: &Upload) -> Result<String, UploadError>;
+	// A short name for the destination, used when reporting on it
+	fn destination(config: &Self::Config) -> String;
+
+	fn upload(config: &Self::Config, photo: &Upload) -> Result<String, UploadError>;
+
+	// Take down a photo, given the id `upload` returned for it
+	fn remove(config: &Self::Config, id: &str) -> Result<(), UploadError>;
+
+	// Push new metadata for a photo that's already been uploaded, and return
+	// its id, which may have changed
+	fn update(config: &Self::Config, id: &str, photo: &Upload) -> Result<String, UploadError>;
+
+	// Describe the request `upload` would make, without making it
+	fn preview(config: &Self::Config, photo: &Upload) -> Result<Value, UploadError>;
 }
 
 #[derive(Clone, Debug)]
-pub struct Upload<'a> {
-	path: &'a str,
-	photo: &'a [u8],
+pub struct Upload {
+	path: String,
 	metadata: Metadata,
 	url: Option<String>,
+	// The hex encoded SHA-256 of the file
+	hash: String,
+}
+
+// Options for changing a photo's metadata before it's published or updated
+fn metadata_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+	vec![
+		Arg::with_name("TITLE")
+			.long("--title")
+			.help("Use this title instead of the one in the photo")
+			.takes_value(true),
+		Arg::with_name("DESCRIPTION")
+			.long("--description")
+			.help("Use this description instead of the one in the photo")
+			.takes_value(true),
+		Arg::with_name("TAG")
+			.long("--tag")
+			.help("Add a tag to the photo's tags. Can be given more than once")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1),
+		Arg::with_name("REMOVE_TAG")
+			.long("--remove-tag")
+			.help("Leave a tag out of the photo's tags. Can be given more than once")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1),
+		Arg::with_name("SET")
+			.long("--set")
+			.value_name("KEY=VALUE")
+			.help(
+				"Set any other field, like `lens=Helios 44-2` or `iso=800`. Can be given more than once",
+			)
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1),
+	]
 }
 
 fn get_matches<'a>() -> ArgMatches<'a> {
 	App::new("photo uploader")
 		.version("0.1")
 		.about("Publish a new photo to several platforms and websites")
+		.setting(AppSettings::SubcommandRequiredElseHelp)
 		.arg(
 			Arg::with_name("DEBUG")
 				.short("-d")
 				.long("--debug")
 				.help("Print extra information to the console")
-				.takes_value(false),
+				.takes_value(false)
+				.global(true),
 		)
-		.arg(
-			Arg::with_name("PATH")
-				.help("The path to the photo to upload")
-				.required(true)
-				.index(1),
+		.subcommand(
+			SubCommand::with_name("publish")
+				.about("Publish photos to every configured destination")
+				.arg(
+					Arg::with_name("DRY_RUN")
+						.short("-n")
+						.long("--dry-run")
+						.help("Show what would be sent to each destination without publishing anything")
+						.takes_value(false),
+				)
+				.arg(
+					Arg::with_name("FORCE")
+						.short("-f")
+						.long("--force")
+						.help("Publish to destinations that already have the photo")
+						.takes_value(false),
+				)
+				.arg(
+					Arg::with_name("KEEP_GOING")
+						.short("-k")
+						.long("--keep-going")
+						.help("Keep publishing to the other destinations when one of them fails")
+						.takes_value(false),
+				)
+				.arg(
+					Arg::with_name("RECURSIVE")
+						.short("-r")
+						.long("--recursive")
+						.help("Look for photos in subdirectories of any directory passed")
+						.takes_value(false),
+				)
+				.args(&metadata_args())
+				.arg(
+					Arg::with_name("PATH")
+						.help("The photos, directories, or globs to upload")
+						.required(true)
+						.multiple(true)
+						.index(1),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("history")
+				.about("List the most recent publications")
+				.arg(
+					Arg::with_name("LIMIT")
+						.short("-l")
+						.long("--limit")
+						.help("How many publications to show")
+						.takes_value(true)
+						.default_value("20"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("status")
+				.about("Show where a photo has been published")
+				.arg(
+					Arg::with_name("PATH")
+						.help("The photo to look up")
+						.required(true)
+						.index(1),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("update")
+				.about(
+					"Push the current title, description, and tags of photos that were already published",
+				)
+				.arg(
+					Arg::with_name("KEEP_GOING")
+						.short("-k")
+						.long("--keep-going")
+						.help("Keep updating the other destinations when one of them fails")
+						.takes_value(false),
+				)
+				.arg(
+					Arg::with_name("RECURSIVE")
+						.short("-r")
+						.long("--recursive")
+						.help("Look for photos in subdirectories of any directory passed")
+						.takes_value(false),
+				)
+				.args(&metadata_args())
+				.arg(
+					Arg::with_name("PATH")
+						.help("The photos, directories, or globs to update")
+						.required(true)
+						.multiple(true)
+						.index(1),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("unpublish")
+				.about("Remove a photo from every destination it was published to")
+				.arg(
+					Arg::with_name("PATH")
+						.help("The photo to remove")
+						.required(true)
+						.index(1),
+				),
 		)
 		.get_matches()
 }
@@ -66,35 +231,12 @@ fn main() -> Result<(), UploadError> {
 		Err(error) => panic!("Could not set up TermLogger {:?}", error),
 	};
 
-	let config = read_config()?;
-	let photo_path = matches.value_of("PATH").unwrap();
-	let metadata = get_metadata(photo_path)?;
-
-	debug!("metadata: {:?}", metadata);
-
-	let mut photo_to_upload = Upload {
-		path: photo_path,
-		photo: &[],
-		metadata: metadata,
-		url: None,
-	};
-
-	let url = match config.cloudinary {
-		Some(cloudinary_config) => Some(Cloudinary::upload(cloudinary_config, &photo_to_upload)?),
-		None => None,
-	};
-
-	photo_to_upload.url = url;
-
-	if let Some(scripts) = config.script {
-		for script in scripts {
-			Script::upload(script, &photo_to_upload)?;
-		}
+	match matches.subcommand() {
+		("publish", Some(publish_matches)) => commands::publish::run(publish_matches),
+		("history", Some(history_matches)) => commands::history::run(history_matches),
+		("status", Some(status_matches)) => commands::status::run(status_matches),
+		("update", Some(update_matches)) => commands::update::run(update_matches),
+		("unpublish", Some(unpublish_matches)) => commands::unpublish::run(unpublish_matches),
+		_ => unreachable!("clap requires a subcommand"),
 	}
-
-	if let Some(flickr_config) = config.flickr {
-		Flickr::upload(flickr_config, &photo_to_upload)?;
-	}
-
-	Ok(())
 }
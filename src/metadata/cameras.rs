@@ -0,0 +1,100 @@
+use crate::UploadError;
+use heck::TitleCase;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const BUNDLED_NAMES: &str = include_str!("cameras.toml");
+
+// Display names for cameras and lenses. The same shape is used for the
+// bundled names and for `[names]` in the config, which takes precedence
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct NamesConfig {
+	// Lowercased make from the EXIF data to the make's name
+	#[serde(default)]
+	pub(crate) makes: BTreeMap<String, String>,
+	// Make's name to model from the EXIF data to the camera's name
+	#[serde(default)]
+	pub(crate) cameras: BTreeMap<String, BTreeMap<String, String>>,
+	// Lens model from the EXIF data to the lens's name
+	#[serde(default)]
+	pub(crate) lenses: BTreeMap<String, String>,
+}
+
+impl NamesConfig {
+	// The bundled names, with any from the config laid over them
+	pub(crate) fn load(overrides: Option<&NamesConfig>) -> Result<NamesConfig, UploadError> {
+		let mut names: NamesConfig = toml::from_str(BUNDLED_NAMES).map_err(|err| {
+			debug!("Error parsing the bundled camera names: {:?}", err);
+
+			UploadError::UnknownError(Some("Unable to parse the bundled camera names".into()))
+		})?;
+
+		if let Some(overrides) = overrides {
+			names.makes.extend(
+				overrides
+					.makes
+					.iter()
+					.map(|(make, name)| (make.to_lowercase(), name.clone())),
+			);
+
+			for (make, models) in &overrides.cameras {
+				names
+					.cameras
+					.entry(make.clone())
+					.or_default()
+					.extend(models.clone());
+			}
+
+			names.lenses.extend(overrides.lenses.clone());
+		}
+
+		Ok(names)
+	}
+
+	fn make(&self, make: &str) -> String {
+		self
+			.makes
+			.get(&make.trim().to_lowercase())
+			.cloned()
+			.unwrap_or_else(|| make.trim().to_title_case())
+	}
+
+	// The camera's name, or the make followed by the model when it isn't known.
+	// Models that already start with the make don't repeat it
+	pub(crate) fn camera(&self, make: Option<&str>, model: Option<&str>) -> String {
+		let make = make.map(|make| self.make(make)).unwrap_or_default();
+		let model = model.unwrap_or_default().trim();
+
+		if let Some(name) = self.cameras.get(&make).and_then(|models| models.get(model)) {
+			return name.clone();
+		}
+
+		join_make(&make, model)
+	}
+
+	// The lens's name, or its make followed by its model when it isn't known
+	pub(crate) fn lens(&self, make: Option<&str>, model: Option<&str>) -> String {
+		let model = model.unwrap_or_default().trim();
+
+		if let Some(name) = self.lenses.get(model) {
+			return name.clone();
+		}
+
+		join_make(&make.map(|make| self.make(make)).unwrap_or_default(), model)
+	}
+}
+
+// Models often start with the make, in capitals, so use the make's name in
+// place of it rather than repeating it
+fn join_make(make: &str, model: &str) -> String {
+	let (first_word, rest) = model.split_once(' ').unwrap_or((model, ""));
+
+	if first_word.eq_ignore_ascii_case(make) {
+		format!("{} {}", make, rest)
+	} else {
+		format!("{} {}", make, model)
+	}
+	.trim()
+	.into()
+}
@@ -1,14 +1,19 @@
-use crate::publishers::cloudinary::CloudinaryConfig;
-use crate::publishers::flickr::FlickrConfig;
-use crate::publishers::script::ScriptConfig;
-use crate::UploadError;
+use crate::metadata::cameras::NamesConfig;
+use crate::metadata::location::LocationConfig;
+use crate::metadata::source::MetadataConfig;
+use crate::metadata::tagging::TagsConfig;
+use crate::metadata::timestamp::TimeConfig;
+use crate::publishers::cloudinary::{Cloudinary, CloudinaryConfig};
+use crate::publishers::flickr::{Flickr, FlickrConfig};
+use crate::publishers::script::{Script, ScriptConfig};
+use crate::{PhotoDestination, UploadError};
 use dirs::home_dir;
 use log::debug;
 use serde::{Deserialize, Serialize};
 use std::fs::{read_to_string, write};
 use std::path::Path;
 
-const CONFIG_PATH: &'static str = ".config/phupload/config.toml";
+const CONFIG_PATH: &str = ".config/phupload/config.toml";
 
 pub(crate) trait PublisherConfig {}
 
@@ -17,12 +22,38 @@ pub(crate) struct Config {
 	pub(crate) cloudinary: Option<CloudinaryConfig>,
 	pub(crate) script: Option<Vec<ScriptConfig>>,
 	pub(crate) flickr: Option<FlickrConfig>,
+	pub(crate) metadata: Option<MetadataConfig>,
+	pub(crate) names: Option<NamesConfig>,
+	pub(crate) location: Option<LocationConfig>,
+	pub(crate) time: Option<TimeConfig>,
+	pub(crate) tags: Option<TagsConfig>,
+}
+
+impl Config {
+	// The names of every configured destination, in the order they're published to
+	pub(crate) fn destinations(&self) -> Vec<String> {
+		let mut destinations: Vec<String> = Vec::new();
+
+		if let Some(cloudinary) = &self.cloudinary {
+			destinations.push(Cloudinary::destination(cloudinary));
+		}
+
+		if let Some(scripts) = &self.script {
+			destinations.extend(scripts.iter().map(Script::destination));
+		}
+
+		if let Some(flickr) = &self.flickr {
+			destinations.push(Flickr::destination(flickr));
+		}
+
+		destinations
+	}
 }
 
 pub(crate) fn read_config() -> Result<Config, UploadError> {
 	let home = home_dir();
 
-	if let None = home {
+	if home.is_none() {
 		return Err(UploadError::MissingConfig(
 			format!("No config was found at $HOME/{}", CONFIG_PATH).into(),
 		));
@@ -46,7 +77,7 @@ pub(crate) fn read_config() -> Result<Config, UploadError> {
 pub(crate) fn write_config(config: Config) -> Result<(), UploadError> {
 	let home = home_dir();
 
-	if let None = home {
+	if home.is_none() {
 		return Err(UploadError::MissingConfig(
 			format!("No config was found at $HOME/{}", CONFIG_PATH).into(),
 		));
@@ -70,3 +101,15 @@ pub(crate) fn write_config(config: Config) -> Result<(), UploadError> {
 		}
 	}
 }
+
+// Hide all but the first few characters of a key or token so it can be shown
+// in dry runs and logs
+pub(crate) fn redact(secret: &str) -> String {
+	let visible: String = secret.chars().take(4).collect();
+
+	if secret.chars().count() <= 4 {
+		"*".repeat(secret.chars().count())
+	} else {
+		format!("{}{}", visible, "*".repeat(secret.chars().count() - 4))
+	}
+}
@@ -0,0 +1,114 @@
+use crate::metadata::tags::Tags;
+use log::warn;
+
+// The size of a photo, both as it's stored and as it's shown. Cameras store
+// portrait photos sideways and record how to turn them in the orientation, so
+// the stored width of a portrait photo is its height
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Dimensions {
+	pub(crate) width: u32,
+	pub(crate) height: u32,
+	// The EXIF orientation, from 1 for upright to 8
+	pub(crate) orientation: u8,
+}
+
+// Parse a whole number of pixels, treating a missing or malformed dimension
+// as unknown
+fn dimension(name: &str, value: &Option<String>) -> Option<u32> {
+	let value = value.as_ref()?;
+
+	match value.parse::<u32>() {
+		Ok(pixels) if pixels > 0 => Some(pixels),
+		_ => {
+			warn!(
+				"Ignoring {}, which should be a positive number of pixels, but was {}",
+				name, value
+			);
+
+			None
+		}
+	}
+}
+
+// exiftool gives the orientation as a number with `-Orientation#`, but
+// overrides files might use its description. Photos without a valid
+// orientation are treated as upright
+fn orientation(value: &Option<String>) -> u8 {
+	let value = match value {
+		Some(value) => value,
+		None => return 1,
+	};
+	let orientation = match value.to_lowercase().as_str() {
+		"horizontal (normal)" => Some(1),
+		"mirror horizontal" => Some(2),
+		"rotate 180" => Some(3),
+		"mirror vertical" => Some(4),
+		"mirror horizontal and rotate 270 cw" => Some(5),
+		"rotate 90 cw" => Some(6),
+		"mirror horizontal and rotate 90 cw" => Some(7),
+		"rotate 270 cw" => Some(8),
+		number => number.parse::<u8>().ok(),
+	};
+
+	match orientation.filter(|orientation| (1..=8).contains(orientation)) {
+		Some(orientation) => orientation,
+		None => {
+			warn!(
+				"Ignoring Orientation, which should be a number from 1 to 8, but was {}",
+				value
+			);
+
+			1
+		}
+	}
+}
+
+impl Dimensions {
+	// Photos without dimensions have unknown dimensions, rather than failing
+	pub(crate) fn from_tags(tags: &Tags) -> Option<Dimensions> {
+		Some(Dimensions {
+			width: dimension("ImageWidth", &tags.image_width)?,
+			height: dimension("ImageHeight", &tags.image_height)?,
+			orientation: orientation(&tags.orientation),
+		})
+	}
+
+	// Orientations 5 to 8 turn the photo a quarter turn
+	fn rotated(&self) -> bool {
+		self.orientation >= 5
+	}
+
+	// The width once the photo is turned upright
+	pub(crate) fn display_width(&self) -> u32 {
+		if self.rotated() {
+			self.height
+		} else {
+			self.width
+		}
+	}
+
+	pub(crate) fn display_height(&self) -> u32 {
+		if self.rotated() {
+			self.width
+		} else {
+			self.height
+		}
+	}
+
+	// Width over height, once the photo is turned upright
+	pub(crate) fn aspect_ratio(&self) -> f64 {
+		self.display_width() as f64 / self.display_height() as f64
+	}
+
+	// The height of the upright photo when it's shown at `width`
+	pub(crate) fn height_at(&self, width: u32) -> u32 {
+		(u64::from(self.display_height()) * u64::from(width) / u64::from(self.display_width())) as u32
+	}
+}
+
+// The height at a width, treating photos without dimensions as square
+pub(crate) fn height_at(dimensions: Option<&Dimensions>, width: u32) -> u32 {
+	dimensions
+		.map(|dimensions| dimensions.height_at(width))
+		.unwrap_or(width)
+}
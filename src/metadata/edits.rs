@@ -0,0 +1,80 @@
+use crate::metadata::config::Config;
+use crate::metadata::exif::Metadata;
+use crate::metadata::tagging::{add_tags, photo_tags};
+use crate::UploadError;
+use clap::ArgMatches;
+
+// Changes to a photo's metadata passed on the command line, for when the
+// metadata in the file is wrong or a single post needs something extra
+#[derive(Debug, Default)]
+pub(crate) struct MetadataEdits {
+	title: Option<String>,
+	description: Option<String>,
+	tags: Vec<String>,
+	removed_tags: Vec<String>,
+	// Any other field, by the name caption templates use
+	fields: Vec<(String, String)>,
+}
+
+impl MetadataEdits {
+	pub(crate) fn from_matches(matches: &ArgMatches) -> Result<MetadataEdits, UploadError> {
+		let values = |name: &str| -> Vec<String> {
+			matches
+				.values_of(name)
+				.map(|values| values.map(String::from).collect())
+				.unwrap_or_default()
+		};
+		let fields = values("SET")
+			.into_iter()
+			.map(|field| match field.split_once('=') {
+				Some((name, value)) => Ok((name.trim().to_string(), value.to_string())),
+				None => Err(UploadError::MalformedMetadata(Some(format!(
+					"--set {} should be in the form key=value",
+					field
+				)))),
+			})
+			.collect::<Result<Vec<(String, String)>, UploadError>>()?;
+
+		Ok(MetadataEdits {
+			title: matches.value_of("TITLE").map(String::from),
+			description: matches.value_of("DESCRIPTION").map(String::from),
+			tags: values("TAG"),
+			removed_tags: values("REMOVE_TAG"),
+			fields,
+		})
+	}
+
+	// Apply the edits over the metadata read from the photo. `--set` goes
+	// first, so the more specific options win when they're both given. Added
+	// tags go through the tag rules like keywords, but removed tags are
+	// removed last, so even default tags can be left off
+	pub(crate) fn apply(&self, metadata: &mut Metadata, config: &Config) -> Result<(), UploadError> {
+		for (name, value) in &self.fields {
+			metadata.set_field(name, value, config.time.as_ref())?;
+
+			// Tags set this way replace the keywords, so they need the rules too
+			if name == "tags" {
+				metadata.tags = photo_tags(config.tags.as_ref(), &metadata.tags);
+			}
+		}
+
+		if let Some(title) = &self.title {
+			metadata.title = title.clone();
+		}
+
+		if let Some(description) = &self.description {
+			metadata.description = description.clone();
+		}
+
+		add_tags(config.tags.as_ref(), &mut metadata.tags, &self.tags);
+
+		metadata.tags.retain(|tag| {
+			!self
+				.removed_tags
+				.iter()
+				.any(|removed| removed.eq_ignore_ascii_case(tag))
+		});
+
+		Ok(())
+	}
+}
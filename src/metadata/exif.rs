@@ -1,147 +1,370 @@
+use crate::metadata::cameras::NamesConfig;
+use crate::metadata::config::Config;
+use crate::metadata::dimensions::Dimensions;
+use crate::metadata::exposure::{Exposure, ShutterSpeed};
+use crate::metadata::location::{Gazetteer, Location};
+use crate::metadata::source::{read_sources, MetadataSource, SourceConfig};
+use crate::metadata::tagging::{photo_tags, TagsConfig};
+use crate::metadata::tags::Tags;
+use crate::metadata::timestamp::{capture_time, parse_time, TimeConfig};
 use crate::UploadError;
+use chrono::{DateTime, FixedOffset};
 use heck::TitleCase;
-use log::debug;
-use regex::Regex;
-use serde::Deserialize;
-use std::collections::HashMap;
+use log::{debug, warn};
+use std::path::Path;
 use std::process::Command;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug)]
 pub(crate) struct Metadata {
 	pub(crate) camera: String,
-	pub(crate) focal_length: String,
-	pub(crate) iso: String,
-	pub(crate) aperture: String,
-	pub(crate) shutter_speed: String,
+	pub(crate) exposure: Exposure,
+	pub(crate) lens: String,
+	pub(crate) exposure_compensation: String,
+	pub(crate) metering_mode: String,
+	pub(crate) flash: String,
+	pub(crate) white_balance: String,
 	pub(crate) title: String,
 	pub(crate) description: String,
 	pub(crate) tags: Vec<String>,
-	pub(crate) height_at_1200: i32,
-	pub(crate) created_at: String,
+	// From -1 for rejected photos up to 5 stars
+	pub(crate) rating: Option<i8>,
+	pub(crate) label: Option<String>,
+	pub(crate) location: Option<Location>,
+	pub(crate) dimensions: Option<Dimensions>,
+	pub(crate) created_at: DateTime<FixedOffset>,
 }
 
-// Convert internal model names to friendly ones
-// NOT COMPREHENSIVE
-fn convert_model(model: &str) -> String {
-	match model {
-		"ILCE-7RM2" => "a7r II".into(),
-		"ILCE-7RM3" => "a7r III".into(),
-		"ILCE-7RM4" => "a7r IV".into(),
-		"ILCE-7SM2" => "a7s II".into(),
-		"ILCE-7SM3" => "a7s III".into(),
-		"ILCE-7SM4" => "a7s IV".into(),
-		_ => model.into(),
+// Reads tags by running exiftool on the photo
+pub(crate) struct Exiftool;
+
+// Every keyword, followed by each level of the hierarchical keywords that
+// isn't already there
+fn keywords(keywords: Option<Vec<String>>, hierarchical: Option<Vec<String>>) -> Vec<String> {
+	let mut tags: Vec<String> = keywords.unwrap_or_default();
+
+	for level in hierarchical
+		.unwrap_or_default()
+		.iter()
+		.flat_map(|path| path.split('|'))
+	{
+		let level = level.trim();
+
+		if !level.is_empty() && !tags.iter().any(|tag| tag == level) {
+			tags.push(level.into());
+		}
 	}
+
+	tags
 }
 
 impl Metadata {
-	fn from_exiftool(output: Vec<u8>) -> Metadata {
-		let mut data: HashMap<String, String> = HashMap::new();
-		let str_output = String::from_utf8(output).unwrap();
-		let lines = str_output.split("\n");
-		let regex = Regex::new("^(?P<k>.+): (?P<v>.+)$").unwrap();
-
-		for line in lines {
-			if let "" = line {
-				continue;
-			}
+	// A field by name, as used in caption templates
+	pub(crate) fn field(&self, name: &str) -> Option<String> {
+		let value = match name {
+			"camera" => self.camera.clone(),
+			"lens" => self.lens.clone(),
+			"focal_length" => self.exposure.focal_length(),
+			"focal_length_35mm" => self.exposure.focal_length_35mm(),
+			"iso" => self.exposure.iso(),
+			"aperture" => self.exposure.aperture(),
+			"shutter_speed" => self.exposure.shutter_speed(),
+			"exposure_compensation" => self.exposure_compensation.clone(),
+			"metering_mode" => self.metering_mode.clone(),
+			"flash" => self.flash.clone(),
+			"white_balance" => self.white_balance.clone(),
+			"title" => self.title.clone(),
+			"description" => self.description.clone(),
+			"tags" => self.tags.join(", "),
+			"rating" => self
+				.rating
+				.map(|rating| rating.to_string())
+				.unwrap_or_default(),
+			"label" => self.label.clone().unwrap_or_default(),
+			"created_at" => self.created_at.to_rfc3339(),
+			"location" => self
+				.location
+				.as_ref()
+				.map(Location::name)
+				.unwrap_or_default(),
+			"city" | "region" | "country" => self
+				.location
+				.as_ref()
+				.and_then(|location| location.place.as_ref())
+				.map(|place| match name {
+					"city" => place.city.clone(),
+					"region" => place.region.clone(),
+					_ => place.country.clone(),
+				})
+				.unwrap_or_default(),
+			"latitude" => self
+				.location
+				.as_ref()
+				.map(|location| location.latitude.to_string())
+				.unwrap_or_default(),
+			"longitude" => self
+				.location
+				.as_ref()
+				.map(|location| location.longitude.to_string())
+				.unwrap_or_default(),
+			// Dimensions are as the photo is shown, turned upright
+			"width" | "height" | "aspect_ratio" => self
+				.dimensions
+				.map(|dimensions| match name {
+					"width" => dimensions.display_width().to_string(),
+					"height" => dimensions.display_height().to_string(),
+					_ => format!("{:.2}", dimensions.aspect_ratio()),
+				})
+				.unwrap_or_default(),
+			_ => return None,
+		};
 
-			let matches = regex.captures(&line).unwrap();
+		Some(value)
+	}
+
+	// Change a field by name, using the same names as caption templates. Empty
+	// values clear fields that can be missing
+	pub(crate) fn set_field(
+		&mut self,
+		name: &str,
+		value: &str,
+		time: Option<&TimeConfig>,
+	) -> Result<(), UploadError> {
+		let malformed =
+			|| UploadError::MalformedMetadata(Some(format!("{} can't be set to {}", name, value)));
+		let value = value.trim();
+		let number = |value: &str| -> Result<Option<f64>, UploadError> {
+			match value.trim_end_matches("mm").trim() {
+				"" => Ok(None),
+				value => value
+					.parse::<f64>()
+					.ok()
+					.filter(|number| *number > 0.0)
+					.map(Some)
+					.ok_or_else(malformed),
+			}
+		};
+		let optional = |value: &str| Some(value.to_string()).filter(|value| !value.is_empty());
 
-			match (matches.name("k"), matches.name("v")) {
-				(Some(key), Some(value)) => {
-					data.insert(key.as_str().into(), value.as_str().into());
+		match name {
+			"camera" => self.camera = value.into(),
+			"lens" => self.lens = value.into(),
+			"focal_length" => self.exposure.focal_length = number(value)?,
+			"focal_length_35mm" => self.exposure.focal_length_35mm = number(value)?,
+			"iso" => self.exposure.iso = number(value)?.map(|iso| iso.round() as u32),
+			"aperture" => self.exposure.aperture = number(value)?,
+			"shutter_speed" => {
+				self.exposure.shutter_speed = match value {
+					"" => None,
+					value => Some(ShutterSpeed::parse(value).ok_or_else(malformed)?),
 				}
-				_ => {}
-			};
+			}
+			"exposure_compensation" => self.exposure_compensation = value.into(),
+			"metering_mode" => self.metering_mode = value.into(),
+			"flash" => self.flash = value.into(),
+			"white_balance" => self.white_balance = value.into(),
+			"title" => self.title = value.into(),
+			"description" => self.description = value.into(),
+			"tags" => {
+				self.tags = value
+					.split(',')
+					.map(|tag| tag.trim().to_string())
+					.filter(|tag| !tag.is_empty())
+					.collect()
+			}
+			"rating" => {
+				self.rating = match value {
+					"" => None,
+					value => Some(value.parse::<i8>().map_err(|_| malformed())?),
+				}
+			}
+			"label" => self.label = optional(value),
+			"created_at" => self.created_at = parse_time(name, value, time)?,
+			_ => {
+				return Err(UploadError::MalformedMetadata(Some(format!(
+					"{} isn't a field that can be set",
+					name
+				))))
+			}
 		}
 
-		Metadata {
-			camera: format!(
-				"{} {}",
-				data
-					.get("Make")
-					.unwrap_or(&String::from(""))
-					.to_title_case(),
-				convert_model(data.get("Model").unwrap_or(&String::from(""))),
-			),
-			focal_length: data.get("FocalLength").unwrap_or(&String::from("")).into(),
-			iso: data.get("ISO").unwrap_or(&String::from("")).into(),
-			aperture: data
-				.get("ApertureValue")
-				.unwrap_or(&String::from("0.0"))
-				.into(),
-			shutter_speed: data
-				.get("ShutterSpeedValue")
-				.unwrap_or(&String::from(""))
-				.into(),
-			title: data
-				.get("Title")
-				.unwrap_or(&String::from(""))
-				.to_title_case(),
-			description: data.get("Description").unwrap_or(&String::from("")).into(),
-			created_at: data
-				.get("DateTimeCreated")
-				.unwrap_or(data.get("DateTimeOriginal").unwrap())
-				.into(),
-			tags: {
-				let tags_str = data.get("Keywords").unwrap_or(&String::from("")).to_owned();
-				let tag_list: Vec<&str> = tags_str.split(", ").collect();
-
-				[tag_list, vec!["upload"]]
-					.concat()
-					.into_iter()
-					.map(|s| String::from(s))
-					.filter(|s| s.len() > 0)
-					.collect()
-			},
-			height_at_1200: {
-				let width = data
-					.get("ImageWidth")
-					.unwrap_or(&String::from("1"))
-					.parse::<i32>()
-					.unwrap_or(1);
-				let height = data
-					.get("ImageHeight")
-					.unwrap_or(&String::from("1"))
-					.parse::<i32>()
-					.unwrap_or(1);
-
-				height * 1200 / width
-			},
+		Ok(())
+	}
+
+	// Build the metadata from the merged tags of every source. Missing tags fall
+	// back to defaults rather than failing, so scans and screenshots can still
+	// be published:
+	// - the title falls back to the file name
+	// - the capture date falls back to when the file was last modified
+	// - photos without dimensions have no width or height
+	// - optional tags that can't be read are left out, with a warning
+	// - anything else is left empty
+	fn from_tags(
+		data: Tags,
+		path: &str,
+		names: &NamesConfig,
+		time: Option<&TimeConfig>,
+		tags: Option<&TagsConfig>,
+	) -> Result<Metadata, UploadError> {
+		let exposure = Exposure::from_tags(&data);
+		let location = Location::from_tags(&data);
+		let created_at = capture_time(&data, path, time)?;
+		let dimensions = Dimensions::from_tags(&data);
+		let rating = data.rating.and_then(|rating| {
+			let parsed = rating.parse::<i8>().ok();
+
+			if parsed.is_none() {
+				warn!(
+					"Ignoring Rating, which should be a whole number, but was {}",
+					rating
+				);
+			}
+
+			parsed
+		});
+		let title = match data.title {
+			Some(title) => title.to_title_case(),
+			None => Path::new(path)
+				.file_stem()
+				.map(|stem| stem.to_string_lossy().to_string())
+				.unwrap_or_default(),
+		};
+
+		Ok(Metadata {
+			camera: names.camera(data.make.as_deref(), data.model.as_deref()),
+			exposure,
+			lens: names.lens(data.lens_make.as_deref(), data.lens_model.as_deref()),
+			exposure_compensation: data.exposure_compensation.unwrap_or_default(),
+			metering_mode: data.metering_mode.unwrap_or_default(),
+			flash: data.flash.unwrap_or_default(),
+			white_balance: data.white_balance.unwrap_or_default(),
+			title,
+			description: data.description.unwrap_or_default(),
+			created_at,
+			tags: photo_tags(tags, &keywords(data.keywords, data.hierarchical_subject)),
+			rating,
+			label: data.label,
+			location,
+			dimensions,
+		})
+	}
+}
+
+impl MetadataSource for Exiftool {
+	fn read(_config: &SourceConfig, path: &str) -> Result<Tags, UploadError> {
+		let exif_return = Command::new("exiftool")
+			.args([
+				"-j",
+				"-EXIF:ISO",
+				"-EXIF:ExposureTime",
+				"-EXIF:FNumber",
+				"-EXIF:ShutterSpeedValue",
+				"-EXIF:ApertureValue",
+				"-EXIF:FocalLength",
+				"-EXIF:Make",
+				"-EXIF:Model",
+				"-LensMake",
+				"-LensModel",
+				"-FocalLengthIn35mmFormat",
+				"-ExposureCompensation",
+				"-MeteringMode",
+				"-Flash",
+				"-WhiteBalance",
+				"-ImageWidth",
+				"-ImageHeight",
+				"-Orientation#",
+				"-Title",
+				"-Keywords",
+				"-HierarchicalSubject",
+				"-Rating",
+				"-Label",
+				"-Description",
+				// Signed decimal degrees, with south and west negative, and meters
+				// below sea level negative
+				"-Composite:GPSLatitude#",
+				"-Composite:GPSLongitude#",
+				"-Composite:GPSAltitude#",
+				// Dates aren't formatted with `-d`, which would give dates without
+				// an offset this computer's, so only the ones that recorded an
+				// offset have one
+				"-DateTimeCreated",
+				"-DateTimeOriginal",
+				"-FileModifyDate",
+				"-OffsetTimeOriginal",
+				"-Composite:GPSDateTime",
+				path,
+			])
+			.output()
+			.map_err(|err| {
+				debug!("Error gathering EXIF data: {:?}", err);
+
+				UploadError::UnknownError(Some(
+					"Error gathering EXIF data. Is exiftool installed?".into(),
+				))
+			})?;
+
+		if !exif_return.status.success() {
+			debug!(
+				"exiftool failed: {}",
+				String::from_utf8_lossy(&exif_return.stderr)
+			);
+
+			return Err(UploadError::MalformedMetadata(Some(format!(
+				"exiftool was unable to read {}",
+				path
+			))));
 		}
+
+		let photos: Vec<Tags> = serde_json::from_slice(&exif_return.stdout).map_err(|err| {
+			debug!("Error parsing exiftool's output: {:?}", err);
+
+			UploadError::MalformedMetadata(Some(format!(
+				"Unable to understand exiftool's output for {}",
+				path
+			)))
+		})?;
+
+		photos.into_iter().next().ok_or_else(|| {
+			UploadError::MissingMetadata(Some(format!("exiftool found no metadata in {}", path)))
+		})
 	}
 }
 
-pub(crate) fn get_metadata(path: &str) -> Result<Metadata, UploadError> {
-	let exif_return = Command::new("exiftool")
-		.args(&[
-			"-S",
-			"-EXIF:ISO",
-			"-EXIF:ShutterSpeedValue",
-			"-EXIF:ApertureValue",
-			"-EXIF:FocalLength",
-			"-EXIF:Make",
-			"-EXIF:Model",
-			"-ImageWidth",
-			"-ImageHeight",
-			"-Title",
-			"-Keywords",
-			"-Description",
-			"-DateTimeCreated",
-			"-DateTimeOriginal",
-			"-d",
-			"%Y-%m-%dT%H:%M:%S%z",
+// Reads photos' metadata, with the camera names and places loaded once for a
+// whole run rather than for every photo
+pub(crate) struct MetadataReader {
+	names: NamesConfig,
+	// Naming places is opt in, since it says more about where a photo was
+	// taken than the coordinates alone
+	gazetteer: Option<Gazetteer>,
+}
+
+impl MetadataReader {
+	pub(crate) fn load(config: &Config) -> Result<MetadataReader, UploadError> {
+		let gazetteer = match &config.location {
+			Some(location_config) if location_config.geocode => Some(Gazetteer::load(location_config)?),
+			_ => None,
+		};
+
+		Ok(MetadataReader {
+			names: NamesConfig::load(config.names.as_ref())?,
+			gazetteer,
+		})
+	}
+
+	// Read a photo's metadata from every configured source
+	pub(crate) fn read(&self, config: &Config, path: &str) -> Result<Metadata, UploadError> {
+		let mut metadata = Metadata::from_tags(
+			read_sources(config.metadata.as_ref(), path)?,
 			path,
-		])
-		.output()
-		.map_err(|err| {
-			debug!("Error gathering EXIF data: {:?}", err);
-
-			UploadError::UnknownError(Some(
-				"Error gathering EXIF data. Is exiftool installed?".into(),
-			))
-		})?;
+			&self.names,
+			config.time.as_ref(),
+			config.tags.as_ref(),
+		)?;
 
-	Ok(Metadata::from_exiftool(exif_return.stdout))
+		if let (Some(gazetteer), Some(location)) = (&self.gazetteer, &mut metadata.location) {
+			location.place = gazetteer.nearest(location);
+		}
+
+		Ok(metadata)
+	}
 }
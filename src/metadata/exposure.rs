@@ -0,0 +1,191 @@
+use crate::metadata::tags::Tags;
+use log::warn;
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+// How long the shutter was open, in seconds, as a fraction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ShutterSpeed {
+	numerator: u32,
+	denominator: u32,
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+	if b == 0 {
+		a
+	} else {
+		gcd(b, a % b)
+	}
+}
+
+// Show a number with as many decimal places as it needs, up to one
+pub(crate) fn decimal(value: f64) -> String {
+	format!("{:.1}", value).trim_end_matches(".0").into()
+}
+
+impl ShutterSpeed {
+	fn new(numerator: u32, denominator: u32) -> Option<ShutterSpeed> {
+		if numerator == 0 || denominator == 0 {
+			return None;
+		}
+
+		let divisor = gcd(numerator, denominator);
+
+		Some(ShutterSpeed {
+			numerator: numerator / divisor,
+			denominator: denominator / divisor,
+		})
+	}
+
+	// Exposure times are written as fractions (`1/250`) or as seconds (`2.5`)
+	pub(crate) fn parse(value: &str) -> Option<ShutterSpeed> {
+		let value = value.trim().trim_end_matches(['s', '"']);
+
+		match value.split_once('/') {
+			Some((numerator, denominator)) => ShutterSpeed::new(
+				numerator.trim().parse().ok()?,
+				denominator.trim().parse().ok()?,
+			),
+			None => ShutterSpeed::from_seconds(value.parse().ok()?),
+		}
+	}
+
+	pub(crate) fn from_seconds(seconds: f64) -> Option<ShutterSpeed> {
+		if !seconds.is_finite() || seconds <= 0.0 {
+			None
+		} else if seconds < 1.0 {
+			// Anything faster than a second is a whole fraction, like 1/250. Slower
+			// ones like 0.3 are kept in tenths
+			let tenths = seconds * 10.0;
+
+			if seconds >= 0.3 && (tenths - tenths.round()).abs() < 0.01 {
+				ShutterSpeed::new(tenths.round() as u32, 10)
+			} else {
+				ShutterSpeed::new(1, (1.0 / seconds).round() as u32)
+			}
+		} else {
+			ShutterSpeed::new((seconds * 10.0).round() as u32, 10)
+		}
+	}
+
+	pub(crate) fn seconds(&self) -> f64 {
+		f64::from(self.numerator) / f64::from(self.denominator)
+	}
+}
+
+// Fast shutter speeds are shown as `1/250`, and slow ones as `2.5"`
+impl fmt::Display for ShutterSpeed {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.numerator == 1 && self.denominator > 1 {
+			write!(f, "1/{}", self.denominator)
+		} else {
+			write!(f, "{}\"", decimal(self.seconds()))
+		}
+	}
+}
+
+impl Serialize for ShutterSpeed {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+// The settings a photo was taken with
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Exposure {
+	// The f-number, like 2.8
+	pub(crate) aperture: Option<f64>,
+	pub(crate) shutter_speed: Option<ShutterSpeed>,
+	pub(crate) iso: Option<u32>,
+	// In millimeters
+	pub(crate) focal_length: Option<f64>,
+	pub(crate) focal_length_35mm: Option<f64>,
+}
+
+// Parse the number at the start of a value, ignoring units like ` mm`.
+// Values that aren't numbers are left out, rather than failing the photo
+fn number(name: &str, value: &Option<String>) -> Option<f64> {
+	let value = value.as_ref()?;
+	let digits: String = value
+		.trim()
+		.chars()
+		.take_while(|c| c.is_ascii_digit() || *c == '.')
+		.collect();
+
+	match digits.parse::<f64>() {
+		Ok(number) if number > 0.0 => Some(number),
+		Ok(_) => None,
+		Err(_) => {
+			warn!(
+				"Ignoring {}, which should be a number, but was {}",
+				name, value
+			);
+
+			None
+		}
+	}
+}
+
+impl Exposure {
+	// Prefer the f-number and exposure time the camera recorded over the APEX
+	// values derived from them, which are rounded
+	pub(crate) fn from_tags(tags: &Tags) -> Exposure {
+		let shutter_speed = tags
+			.exposure_time
+			.as_ref()
+			.or(tags.shutter_speed_value.as_ref())
+			.and_then(|value| {
+				let shutter_speed = ShutterSpeed::parse(value);
+
+				if shutter_speed.is_none() {
+					warn!(
+						"Ignoring ExposureTime, which should be a number of seconds, but was {}",
+						value
+					);
+				}
+
+				shutter_speed
+			});
+		let aperture = match &tags.f_number {
+			Some(_) => number("FNumber", &tags.f_number),
+			None => number("ApertureValue", &tags.aperture_value),
+		};
+
+		Exposure {
+			aperture,
+			shutter_speed,
+			iso: number("ISO", &tags.iso).map(|iso| iso.round() as u32),
+			focal_length: number("FocalLength", &tags.focal_length),
+			focal_length_35mm: number("FocalLengthIn35mmFormat", &tags.focal_length_in_35mm_format),
+		}
+	}
+
+	pub(crate) fn aperture(&self) -> String {
+		self.aperture.map(decimal).unwrap_or_default()
+	}
+
+	pub(crate) fn shutter_speed(&self) -> String {
+		self
+			.shutter_speed
+			.map(|shutter_speed| shutter_speed.to_string())
+			.unwrap_or_default()
+	}
+
+	pub(crate) fn iso(&self) -> String {
+		self.iso.map(|iso| iso.to_string()).unwrap_or_default()
+	}
+
+	pub(crate) fn focal_length(&self) -> String {
+		self
+			.focal_length
+			.map(|focal_length| format!("{} mm", decimal(focal_length)))
+			.unwrap_or_default()
+	}
+
+	pub(crate) fn focal_length_35mm(&self) -> String {
+		self
+			.focal_length_35mm
+			.map(|focal_length| format!("{} mm", decimal(focal_length)))
+			.unwrap_or_default()
+	}
+}
@@ -0,0 +1,57 @@
+use crate::metadata::template::render_with;
+use crate::{Upload, UploadError};
+
+// Unique to the photo's contents, so photos with the same title don't replace
+// each other, and still readable when browsing a destination
+const DEFAULT_ID_TEMPLATE: &str = "{slug}-{short_hash}";
+// Long enough that two photos in a library won't share it
+const SHORT_HASH_LENGTH: usize = 12;
+// Characters that destinations treat specially in ids and URLs. Slashes in the
+// template itself are kept, since Cloudinary uses them for folders
+const UNSAFE: [char; 10] = ['?', '&', '#', '\\', '%', '<', '>', '+', ':', '/'];
+
+// A field's value with spaces as dashes and anything that isn't safe in an id
+// removed, so `{title}` gives `Evening-Light` for `Evening Light`
+fn id_safe(value: &str) -> String {
+	value
+		.split_whitespace()
+		.collect::<Vec<&str>>()
+		.join("-")
+		.chars()
+		.filter(|c| !UNSAFE.contains(c) && !c.is_control())
+		.collect()
+}
+
+// A title as lowercase words separated by dashes, like `new-york-at-night` for
+// `New York, at night!`
+pub(crate) fn slug(title: &str) -> String {
+	title
+		.split(|c: char| !c.is_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.map(|word| word.to_lowercase())
+		.collect::<Vec<String>>()
+		.join("-")
+}
+
+// The id a destination gives a photo, from a template that can use `slug`,
+// `date`, `hash`, and `short_hash` on top of the caption template fields,
+// which are made safe to use in an id. Separators left dangling by empty
+// fields are trimmed, and photos that end up without any id fall back to
+// their hash
+pub(crate) fn id(template: Option<&str>, photo: &Upload) -> Result<String, UploadError> {
+	let template = template.unwrap_or(DEFAULT_ID_TEMPLATE);
+	let rendered = render_with(template, |name| match name {
+		"slug" => Some(slug(&photo.metadata.title)),
+		"date" => Some(photo.metadata.created_at.format("%Y-%m-%d").to_string()),
+		"hash" => Some(photo.hash.clone()),
+		"short_hash" => Some(photo.hash.chars().take(SHORT_HASH_LENGTH).collect()),
+		name => photo.metadata.field(name).map(|value| id_safe(&value)),
+	})?;
+	let id = rendered.trim_matches(|c: char| c == '-' || c == '_' || c == '/' || c.is_whitespace());
+
+	Ok(if id.is_empty() {
+		photo.hash.clone()
+	} else {
+		id.into()
+	})
+}
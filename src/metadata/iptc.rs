@@ -0,0 +1,112 @@
+use std::ops::Range;
+
+// IPTC-IIM records are stored in a Photoshop resource block (APP13 in JPEGs).
+// Every dataset starts with a tag marker, the record number, the dataset
+// number, and a two byte length
+const TAG_MARKER: u8 = 0x1C;
+const APPLICATION_RECORD: u8 = 0x02;
+// The record version dataset always comes first in the application record
+const RECORD_START: [u8; 5] = [TAG_MARKER, APPLICATION_RECORD, 0x00, 0x00, 0x02];
+
+#[cfg(feature = "native-metadata")]
+const OBJECT_NAME: u8 = 5;
+#[cfg(feature = "native-metadata")]
+const KEYWORDS: u8 = 25;
+#[cfg(feature = "native-metadata")]
+const DATE_CREATED: u8 = 55;
+#[cfg(feature = "native-metadata")]
+const TIME_CREATED: u8 = 60;
+#[cfg(feature = "native-metadata")]
+const CAPTION: u8 = 120;
+// Place names, which privacy settings can remove
+pub(crate) const CITY: u8 = 90;
+pub(crate) const SUBLOCATION: u8 = 92;
+pub(crate) const PROVINCE: u8 = 95;
+pub(crate) const COUNTRY_CODE: u8 = 100;
+pub(crate) const COUNTRY: u8 = 101;
+
+// The fields phupload cares about from an IPTC block
+#[cfg(feature = "native-metadata")]
+#[derive(Debug, Default)]
+pub(crate) struct Iptc {
+	pub(crate) object_name: Option<String>,
+	pub(crate) caption: Option<String>,
+	pub(crate) keywords: Vec<String>,
+	pub(crate) date_created: Option<String>,
+	pub(crate) time_created: Option<String>,
+}
+
+// Values are UTF-8 in anything written in the last decade, and usually
+// Latin-1 before that
+#[cfg(feature = "native-metadata")]
+fn decode(bytes: &[u8]) -> String {
+	match std::str::from_utf8(bytes) {
+		Ok(value) => value.trim().into(),
+		Err(_) => bytes
+			.iter()
+			.map(|byte| *byte as char)
+			.collect::<String>()
+			.trim()
+			.into(),
+	}
+}
+
+// The dataset number and where the value is, for every dataset in the
+// application record
+fn datasets(bytes: &[u8]) -> Option<Vec<(u8, Range<usize>)>> {
+	let mut offset = bytes
+		.windows(RECORD_START.len())
+		.position(|window| window == RECORD_START)?;
+	let mut datasets = Vec::new();
+
+	while offset + 5 <= bytes.len() && bytes[offset] == TAG_MARKER {
+		let record = bytes[offset + 1];
+		let dataset = bytes[offset + 2];
+		let length = u16::from_be_bytes([bytes[offset + 3], bytes[offset + 4]]) as usize;
+		let start = offset + 5;
+
+		// Extended datasets (with the high bit of the length set) are never
+		// used for the fields here, so stop rather than guess at them
+		if length & 0x8000 != 0 || start + length > bytes.len() {
+			break;
+		}
+
+		if record == APPLICATION_RECORD {
+			datasets.push((dataset, start..start + length));
+		}
+
+		offset = start + length;
+	}
+
+	Some(datasets)
+}
+
+#[cfg(feature = "native-metadata")]
+pub(crate) fn parse(bytes: &[u8]) -> Option<Iptc> {
+	let mut iptc = Iptc::default();
+
+	for (dataset, range) in datasets(bytes)? {
+		let value = decode(&bytes[range]);
+
+		match dataset {
+			OBJECT_NAME => iptc.object_name = Some(value),
+			CAPTION => iptc.caption = Some(value),
+			KEYWORDS => iptc.keywords.push(value),
+			DATE_CREATED => iptc.date_created = Some(value),
+			TIME_CREATED => iptc.time_created = Some(value),
+			_ => {}
+		}
+	}
+
+	Some(iptc)
+}
+
+// Blank out the values of some datasets with spaces, so nothing else in the
+// file moves. Readers trim values, so they come out empty
+pub(crate) fn blank(bytes: &mut [u8], blanked: &[u8]) {
+	for (dataset, range) in datasets(bytes).unwrap_or_default() {
+		if blanked.contains(&dataset) {
+			bytes[range].fill(b' ');
+		}
+	}
+}
@@ -0,0 +1,276 @@
+use crate::UploadError;
+use chrono::{DateTime, Utc};
+use dirs::home_dir;
+use log::debug;
+use ring::digest::{Context, SHA256};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write, File};
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+const LEDGER_PATH: &str = ".config/phupload/ledger.json";
+
+// Where a photo was published, and what the destination called it
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Publication {
+	pub(crate) id: String,
+	pub(crate) published_at: DateTime<Utc>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub(crate) updated_at: Option<DateTime<Utc>>,
+}
+
+// The last error a destination gave when publishing a photo
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Failure {
+	pub(crate) error: String,
+	pub(crate) failed_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct LedgerEntry {
+	pub(crate) path: String,
+	pub(crate) title: String,
+	pub(crate) publications: BTreeMap<String, Publication>,
+	#[serde(default)]
+	pub(crate) failures: BTreeMap<String, Failure>,
+}
+
+// Every photo that has been published, keyed by the SHA-256 of its contents
+// so that renaming or moving a file doesn't publish it again
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Ledger {
+	pub(crate) photos: BTreeMap<String, LedgerEntry>,
+}
+
+impl Ledger {
+	pub(crate) fn publication(&self, hash: &str, destination: &str) -> Option<&Publication> {
+		self
+			.photos
+			.get(hash)
+			.and_then(|entry| entry.publications.get(destination))
+	}
+
+	// Look a photo up by its hash, falling back to the path it was published
+	// from in case the file has been edited since
+	pub(crate) fn find(&self, hash: &str, path: &str) -> Option<(&String, &LedgerEntry)> {
+		let path = absolute_path(path);
+
+		self
+			.photos
+			.get_key_value(hash)
+			.or_else(|| self.photos.iter().find(|(_, entry)| entry.path == path))
+	}
+
+	fn entry(&mut self, hash: &str, path: &str, title: &str) -> &mut LedgerEntry {
+		let entry = self
+			.photos
+			.entry(hash.into())
+			.or_insert_with(|| LedgerEntry {
+				path: String::new(),
+				title: String::new(),
+				publications: BTreeMap::new(),
+				failures: BTreeMap::new(),
+			});
+
+		entry.path = absolute_path(path);
+		entry.title = title.into();
+
+		entry
+	}
+
+	pub(crate) fn record(
+		&mut self,
+		hash: &str,
+		path: &str,
+		title: &str,
+		destination: &str,
+		id: &str,
+	) {
+		let entry = self.entry(hash, path, title);
+
+		entry.failures.remove(destination);
+		entry.publications.insert(
+			destination.into(),
+			Publication {
+				id: id.into(),
+				published_at: Utc::now(),
+				updated_at: None,
+			},
+		);
+	}
+
+	// Note that a photo's metadata was pushed to a destination it was already
+	// published to
+	pub(crate) fn record_update(
+		&mut self,
+		hash: &str,
+		path: &str,
+		title: &str,
+		destination: &str,
+		id: &str,
+	) {
+		let entry = self.entry(hash, path, title);
+
+		entry.failures.remove(destination);
+
+		if let Some(publication) = entry.publications.get_mut(destination) {
+			publication.id = id.into();
+			publication.updated_at = Some(Utc::now());
+		}
+	}
+
+	// Move a photo to a new hash once its file has been edited. If the new
+	// contents were already published on their own, the two entries are merged,
+	// keeping the most recent publication for each destination
+	pub(crate) fn rehash(&mut self, old_hash: &str, new_hash: &str) {
+		let mut entry = match self.photos.remove(old_hash) {
+			Some(entry) => entry,
+			None => return,
+		};
+
+		if let Some(existing) = self.photos.remove(new_hash) {
+			for (destination, publication) in existing.publications {
+				let newer = entry
+					.publications
+					.get(&destination)
+					.map(|kept| publication.published_at > kept.published_at)
+					.unwrap_or(true);
+
+				if newer {
+					entry.failures.remove(&destination);
+					entry.publications.insert(destination, publication);
+				}
+			}
+
+			for (destination, failure) in existing.failures {
+				if !entry.publications.contains_key(&destination) {
+					entry.failures.entry(destination).or_insert(failure);
+				}
+			}
+		}
+
+		self.photos.insert(new_hash.into(), entry);
+	}
+
+	pub(crate) fn record_failure(
+		&mut self,
+		hash: &str,
+		path: &str,
+		title: &str,
+		destination: &str,
+		error: &UploadError,
+	) {
+		let entry = self.entry(hash, path, title);
+
+		entry.failures.insert(
+			destination.into(),
+			Failure {
+				error: error.to_string(),
+				failed_at: Utc::now(),
+			},
+		);
+	}
+
+	// Forget a photo was ever sent to a destination. Photos that aren't on any
+	// destination anymore are dropped from the ledger entirely
+	pub(crate) fn remove(&mut self, hash: &str, destination: &str) {
+		if let Some(entry) = self.photos.get_mut(hash) {
+			entry.publications.remove(destination);
+			entry.failures.remove(destination);
+
+			if entry.publications.is_empty() && entry.failures.is_empty() {
+				self.photos.remove(hash);
+			}
+		}
+	}
+}
+
+fn absolute_path(path: &str) -> String {
+	Path::new(path)
+		.canonicalize()
+		.map(|path| path.to_string_lossy().to_string())
+		.unwrap_or_else(|_| path.into())
+}
+
+fn ledger_path() -> Result<PathBuf, UploadError> {
+	match home_dir() {
+		Some(home) => Ok(home.join(Path::new(LEDGER_PATH))),
+		None => Err(UploadError::MissingConfig(Some(
+			"Unable to find your home directory to store the ledger".into(),
+		))),
+	}
+}
+
+// A missing ledger just means nothing has been published yet
+pub(crate) fn read_ledger() -> Result<Ledger, UploadError> {
+	let raw_ledger = match read_to_string(ledger_path()?) {
+		Ok(raw_ledger) => raw_ledger,
+		Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(Ledger::default()),
+		Err(err) => {
+			debug!("Error reading the ledger: {:?}", err);
+
+			return Err(UploadError::UnknownError(Some(format!(
+				"Unable to read the ledger at $HOME/{}",
+				LEDGER_PATH
+			))));
+		}
+	};
+
+	serde_json::from_str(&raw_ledger).map_err(|err| {
+		debug!("Error parsing the ledger: {:?}", err);
+
+		UploadError::MalformedConfig(Some(format!(
+			"Unable to parse the ledger at $HOME/{}",
+			LEDGER_PATH
+		)))
+	})
+}
+
+pub(crate) fn write_ledger(ledger: &Ledger) -> Result<(), UploadError> {
+	let json_ledger = serde_json::to_string_pretty(ledger).map_err(|err| {
+		debug!("Error serializing the ledger: {:?}", err);
+
+		UploadError::UnknownError(Some("Error serializing the ledger".into()))
+	})?;
+
+	write(ledger_path()?, json_ledger).map_err(|err| {
+		debug!("Error saving the ledger: {:?}", err);
+
+		UploadError::UnknownError(Some(format!(
+			"Error saving the ledger at $HOME/{}",
+			LEDGER_PATH
+		)))
+	})
+}
+
+// The hex encoded SHA-256 of a file's contents
+pub(crate) fn content_hash(path: &str) -> Result<String, UploadError> {
+	let read_error = |err| {
+		debug!("Error reading {} to hash it: {:?}", path, err);
+
+		UploadError::UnknownError(Some(format!("Unable to read the photo at {}", path)))
+	};
+	let mut file = File::open(path).map_err(read_error)?;
+	let mut context = Context::new(&SHA256);
+	let mut buffer = [0; 64 * 1024];
+
+	loop {
+		let read = file.read(&mut buffer).map_err(read_error)?;
+
+		if read == 0 {
+			break;
+		}
+
+		context.update(&buffer[..read]);
+	}
+
+	Ok(
+		context
+			.finish()
+			.as_ref()
+			.iter()
+			.map(|byte| format!("{:02x}", byte))
+			.collect(),
+	)
+}
@@ -0,0 +1,203 @@
+use crate::metadata::overrides::expand_home;
+use crate::metadata::tags::Tags;
+use crate::UploadError;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::read_to_string;
+
+const BUNDLED_PLACES: &str = include_str!("places.csv");
+const EARTH_RADIUS_KM: f64 = 6371.0;
+const KM_PER_DEGREE: f64 = 111.32;
+const DEFAULT_MAX_DISTANCE_KM: f64 = 50.0;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct LocationConfig {
+	// Name the place each photo was taken near, from the bundled places
+	#[serde(default)]
+	pub(crate) geocode: bool,
+	// A CSV of more places, in the same format as the bundled ones
+	pub(crate) places: Option<String>,
+	// How far a photo can be from a place to be named after it
+	pub(crate) max_distance_km: Option<f64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Place {
+	pub(crate) city: String,
+	pub(crate) region: String,
+	pub(crate) country: String,
+	#[serde(skip)]
+	latitude: f64,
+	#[serde(skip)]
+	longitude: f64,
+}
+
+// Where a photo was taken, in degrees, and meters above sea level
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Location {
+	pub(crate) latitude: f64,
+	pub(crate) longitude: f64,
+	pub(crate) altitude: Option<f64>,
+	#[serde(flatten)]
+	pub(crate) place: Option<Place>,
+}
+
+// Coordinates are kept to six decimal places, which is about 10 cm, so the
+// sums used to convert them from minutes and seconds don't show
+fn six_places(degrees: f64) -> f64 {
+	(degrees * 1e6).round() / 1e6
+}
+
+fn coordinate(name: &str, value: &str, limit: f64) -> Option<f64> {
+	match value.trim().parse::<f64>() {
+		Ok(degrees) if degrees.abs() <= limit => Some(six_places(degrees)),
+		_ => {
+			warn!(
+				"Ignoring {}, which should be a number of degrees, but was {}",
+				name, value
+			);
+
+			None
+		}
+	}
+}
+
+// The distance between two points on the globe, in kilometers
+fn distance(from: (f64, f64), to: (f64, f64)) -> f64 {
+	let (from_latitude, to_latitude) = (from.0.to_radians(), to.0.to_radians());
+	let latitude_delta = (to.0 - from.0).to_radians();
+	let longitude_delta = (to.1 - from.1).to_radians();
+	let a = (latitude_delta / 2.0).sin().powi(2)
+		+ from_latitude.cos() * to_latitude.cos() * (longitude_delta / 2.0).sin().powi(2);
+
+	2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+impl Location {
+	// The GPS position, if the photo has one. Sources give coordinates as signed
+	// decimal degrees, with south and west negative. A position that can't be
+	// read is left out, rather than failing the photo
+	pub(crate) fn from_tags(tags: &Tags) -> Option<Location> {
+		let latitude = coordinate("GPSLatitude", tags.gps_latitude.as_ref()?, 90.0);
+		let longitude = coordinate("GPSLongitude", tags.gps_longitude.as_ref()?, 180.0);
+		let altitude = tags.gps_altitude.as_ref().and_then(|altitude| {
+			let meters = altitude.trim().parse::<f64>().ok();
+
+			if meters.is_none() {
+				warn!(
+					"Ignoring GPSAltitude, which should be a number of meters, but was {}",
+					altitude
+				);
+			}
+
+			meters
+		});
+
+		Some(Location {
+			latitude: latitude?,
+			longitude: longitude?,
+			altitude,
+			place: None,
+		})
+	}
+
+	// The location snapped to a grid with squares about `km` wide, so it only
+	// says roughly where the photo was taken. The altitude and place are
+	// dropped, since they can narrow that back down
+	pub(crate) fn rounded(&self, km: f64) -> Location {
+		let snap = |degrees: f64, step: f64| six_places((degrees / step).round() * step);
+		let latitude = snap(self.latitude, km / KM_PER_DEGREE).clamp(-90.0, 90.0);
+		// Degrees of longitude get narrower towards the poles
+		let longitude_step = km / (KM_PER_DEGREE * latitude.to_radians().cos().max(0.01));
+		let longitude = snap(self.longitude, longitude_step).clamp(-180.0, 180.0);
+
+		Location {
+			latitude,
+			longitude,
+			altitude: None,
+			place: None,
+		}
+	}
+
+	// The place's name, like `Portland, Oregon, United States`, or the
+	// coordinates when there isn't one
+	pub(crate) fn name(&self) -> String {
+		match &self.place {
+			Some(place) => format!("{}, {}, {}", place.city, place.region, place.country),
+			None => format!("{:.4}, {:.4}", self.latitude, self.longitude),
+		}
+	}
+}
+
+// An offline list of places to name photos after
+pub(crate) struct Gazetteer {
+	places: Vec<Place>,
+	max_distance_km: f64,
+}
+
+fn parse_places(source: &str, contents: &str) -> Result<Vec<Place>, UploadError> {
+	contents
+		.lines()
+		.map(|line| line.trim())
+		.filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("name,"))
+		.map(|line| {
+			let columns: Vec<&str> = line.split(',').map(|column| column.trim()).collect();
+			let malformed = || {
+				UploadError::MalformedConfig(Some(format!(
+					"Unable to parse the place \"{}\" in {}",
+					line, source
+				)))
+			};
+
+			if columns.len() != 5 {
+				return Err(malformed());
+			}
+
+			Ok(Place {
+				city: columns[0].into(),
+				region: columns[1].into(),
+				country: columns[2].into(),
+				latitude: columns[3].parse().map_err(|_| malformed())?,
+				longitude: columns[4].parse().map_err(|_| malformed())?,
+			})
+		})
+		.collect()
+}
+
+impl Gazetteer {
+	pub(crate) fn load(config: &LocationConfig) -> Result<Gazetteer, UploadError> {
+		let mut places = parse_places("the bundled places", BUNDLED_PLACES)?;
+
+		if let Some(path) = &config.places {
+			let path = expand_home(path);
+			let contents = read_to_string(&path).map_err(|err| {
+				debug!("Error reading the places file {:?}: {:?}", path, err);
+
+				UploadError::MissingConfig(Some(format!(
+					"Unable to read the places at {}",
+					path.display()
+				)))
+			})?;
+
+			places.extend(parse_places(&path.display().to_string(), &contents)?);
+		}
+
+		Ok(Gazetteer {
+			places,
+			max_distance_km: config.max_distance_km.unwrap_or(DEFAULT_MAX_DISTANCE_KM),
+		})
+	}
+
+	// The closest place to a location, if any are close enough
+	pub(crate) fn nearest(&self, location: &Location) -> Option<Place> {
+		let position = (location.latitude, location.longitude);
+
+		self
+			.places
+			.iter()
+			.map(|place| (distance(position, (place.latitude, place.longitude)), place))
+			.filter(|(distance, _)| *distance <= self.max_distance_km)
+			.min_by(|(a, _), (b, _)| a.total_cmp(b))
+			.map(|(_, place)| place.clone())
+	}
+}
@@ -1,2 +1,21 @@
+pub(crate) mod cameras;
 pub(crate) mod config;
+pub(crate) mod dimensions;
+pub(crate) mod edits;
 pub(crate) mod exif;
+pub(crate) mod exposure;
+pub(crate) mod identity;
+pub(crate) mod iptc;
+pub(crate) mod ledger;
+pub(crate) mod location;
+#[cfg(feature = "native-metadata")]
+pub(crate) mod native;
+pub(crate) mod overrides;
+pub(crate) mod privacy;
+pub(crate) mod sidecar;
+pub(crate) mod source;
+pub(crate) mod tagging;
+pub(crate) mod tags;
+pub(crate) mod template;
+pub(crate) mod timestamp;
+pub(crate) mod xmp;
@@ -0,0 +1,412 @@
+use crate::metadata::exposure::ShutterSpeed;
+use crate::metadata::source::{MetadataSource, SourceConfig};
+use crate::metadata::tags::{non_empty, Tags};
+use crate::metadata::{iptc, xmp};
+use crate::UploadError;
+use ::exif::experimental::Writer;
+use ::exif::{Context, Exif, In, Reader, Tag, Value};
+use chrono::{DateTime, Local};
+use log::debug;
+use std::fs::{metadata, read, File};
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+// The TIFF tags that describe the photo rather than how its image data is
+// stored, which would be wrong for a copy
+const DESCRIPTIVE_TIFF_TAGS: [Tag; 10] = [
+	Tag::ImageDescription,
+	Tag::Make,
+	Tag::Model,
+	Tag::Orientation,
+	Tag::XResolution,
+	Tag::YResolution,
+	Tag::ResolutionUnit,
+	Tag::Software,
+	Tag::DateTime,
+	Tag::Artist,
+];
+
+fn ascii(exif: Option<&Exif>, tag: Tag) -> Option<String> {
+	match &exif?.get_field(tag, In::PRIMARY)?.value {
+		Value::Ascii(values) => values
+			.first()
+			.map(|value| String::from_utf8_lossy(value).trim().to_string())
+			.filter(|value| !value.is_empty()),
+		_ => None,
+	}
+}
+
+fn number(exif: Option<&Exif>, tag: Tag) -> Option<f64> {
+	match &exif?.get_field(tag, In::PRIMARY)?.value {
+		Value::Rational(values) => values.first().map(|value| value.to_f64()),
+		Value::SRational(values) => values.first().map(|value| value.to_f64()),
+		value => value.get_uint(0).map(f64::from),
+	}
+	.filter(|value| value.is_finite())
+}
+
+// Keep rationals like the exposure time as fractions, so they aren't rounded
+fn rational(exif: Option<&Exif>, tag: Tag) -> Option<String> {
+	match &exif?.get_field(tag, In::PRIMARY)?.value {
+		Value::Rational(values) => values
+			.first()
+			.filter(|value| value.denom != 0)
+			.map(|value| format!("{}/{}", value.num, value.denom)),
+		_ => None,
+	}
+}
+
+// Exposure compensation is shown in thirds or halves of a stop, like `+2/3`
+fn exposure_compensation(stops: f64) -> String {
+	if stops.abs() < 0.001 {
+		return "0".into();
+	}
+
+	for denominator in 1..=3 {
+		let numerator = stops * f64::from(denominator);
+
+		if (numerator - numerator.round()).abs() < 0.01 {
+			return match denominator {
+				1 => format!("{:+}", numerator.round()),
+				_ => format!("{:+}/{}", numerator.round(), denominator),
+			};
+		}
+	}
+
+	format!("{:+.2}", stops)
+}
+
+fn metering_mode(mode: u32) -> String {
+	match mode {
+		1 => "Average",
+		2 => "Center-weighted average",
+		3 => "Spot",
+		4 => "Multi-spot",
+		5 => "Multi-segment",
+		6 => "Partial",
+		255 => "Other",
+		_ => "Unknown",
+	}
+	.into()
+}
+
+// The flash tag packs whether it fired, its mode, and red-eye reduction into
+// bits. These are the combinations cameras actually write
+fn flash(value: u32) -> String {
+	match value {
+		0x00 => "No Flash",
+		0x01 => "Fired",
+		0x05 => "Fired, Return not detected",
+		0x07 => "Fired, Return detected",
+		0x08 => "On, Did not fire",
+		0x09 => "On, Fired",
+		0x0d => "On, Return not detected",
+		0x0f => "On, Return detected",
+		0x10 => "Off, Did not fire",
+		0x18 => "Auto, Did not fire",
+		0x19 => "Auto, Fired",
+		0x1d => "Auto, Fired, Return not detected",
+		0x1f => "Auto, Fired, Return detected",
+		0x20 => "No flash function",
+		0x41 => "Fired, Red-eye reduction",
+		0x49 => "On, Red-eye reduction",
+		0x50 => "Off, Red-eye reduction",
+		0x58 => "Auto, Did not fire, Red-eye reduction",
+		0x59 => "Auto, Fired, Red-eye reduction",
+		value if value & 1 == 1 => "Fired",
+		_ => "Did not fire",
+	}
+	.into()
+}
+
+fn white_balance(mode: u32) -> String {
+	match mode {
+		0 => "Auto",
+		_ => "Manual",
+	}
+	.into()
+}
+
+// GPS coordinates are stored as degrees, minutes, and seconds, with a
+// reference saying which hemisphere they're in
+fn coordinate(exif: Option<&Exif>, tag: Tag, reference: Tag, negative: &str) -> Option<String> {
+	let degrees = match &exif?.get_field(tag, In::PRIMARY)?.value {
+		Value::Rational(values) if values.len() == 3 => values
+			.iter()
+			.zip([1.0, 60.0, 3600.0])
+			.map(|(value, divisor)| value.to_f64() / divisor)
+			.sum::<f64>(),
+		_ => return None,
+	};
+	let sign = match ascii(exif, reference) {
+		Some(reference) if reference.eq_ignore_ascii_case(negative) => -1.0,
+		_ => 1.0,
+	};
+
+	Some((sign * degrees).to_string()).filter(|_| degrees.is_finite())
+}
+
+// Altitudes below sea level have a reference of 1
+fn altitude(exif: Option<&Exif>) -> Option<String> {
+	let meters = number(exif, Tag::GPSAltitude)?;
+	let sign = match number(exif, Tag::GPSAltitudeRef) {
+		Some(reference) if reference as u32 == 1 => -1.0,
+		_ => 1.0,
+	};
+
+	Some((sign * meters).to_string())
+}
+
+// The width and height from the start of frame marker of a JPEG, which is
+// what exiftool reports as the image size
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+	if !bytes.starts_with(&[0xFF, 0xD8]) {
+		return None;
+	}
+
+	let mut offset = 2;
+
+	while offset + 9 < bytes.len() {
+		if bytes[offset] != 0xFF {
+			return None;
+		}
+
+		// Any number of fill bytes can come before a marker
+		if bytes[offset + 1] == 0xFF {
+			offset += 1;
+			continue;
+		}
+
+		let marker = bytes[offset + 1];
+		let length = u16::from_be_bytes([bytes[offset + 2], bytes[offset + 3]]) as usize;
+
+		// SOF0 through SOF15, other than DHT, JPG, and DAC
+		if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+			let height = u16::from_be_bytes([bytes[offset + 5], bytes[offset + 6]]);
+			let width = u16::from_be_bytes([bytes[offset + 7], bytes[offset + 8]]);
+
+			return Some((u32::from(width), u32::from(height)));
+		}
+
+		offset += 2 + length;
+	}
+
+	None
+}
+
+// `YYYY:MM:DD HH:MM:SS` to `YYYY-MM-DDTHH:MM:SS`. Like exiftool, the offset
+// is left to `OffsetTimeOriginal`
+fn exif_date(date: &str) -> Option<String> {
+	let (day, time) = date.split_at(date.find(' ')?);
+
+	Some(format!("{}T{}", day.replace(':', "-"), time.trim()))
+}
+
+// The GPS date and time are separate tags, and always in UTC
+fn gps_date(exif: Option<&Exif>) -> Option<String> {
+	let day = ascii(exif, Tag::GPSDateStamp)?;
+	let time = match &exif?.get_field(Tag::GPSTimeStamp, In::PRIMARY)?.value {
+		Value::Rational(values) if values.len() == 3 => values
+			.iter()
+			.map(|value| value.to_f64())
+			.collect::<Vec<f64>>(),
+		_ => return None,
+	};
+
+	Some(format!(
+		"{}T{:02}:{:02}:{:02}+0000",
+		day.replace(':', "-"),
+		time[0] as u32,
+		time[1] as u32,
+		time[2] as u32
+	))
+	.filter(|_| time.iter().all(|value| value.is_finite()))
+}
+
+fn digits(value: &str) -> bool {
+	value.chars().all(|c| c.is_ascii_digit())
+}
+
+// IPTC dates are `YYYYMMDD`, and times are `HHMMSS±HHMM`. They're checked
+// before they're sliced, since text that isn't ASCII can get through
+fn iptc_date(date: &str, time: Option<&str>) -> Option<String> {
+	if date.len() != 8 || !digits(date) {
+		return None;
+	}
+
+	let time = time.unwrap_or("000000");
+	let clock = time.get(0..6)?;
+	let zone = time.get(6..)?;
+	let zone_digits = zone.strip_prefix('+').or_else(|| zone.strip_prefix('-'));
+	let valid_zone = match zone_digits {
+		Some(zone_digits) => zone_digits.len() == 4 && digits(zone_digits),
+		None => zone.is_empty(),
+	};
+
+	if !digits(clock) || !valid_zone {
+		return None;
+	}
+
+	Some(format!(
+		"{}-{}-{}T{}:{}:{}{}",
+		&date[0..4],
+		&date[4..6],
+		&date[6..8],
+		&clock[0..2],
+		&clock[2..4],
+		&clock[4..6],
+		zone
+	))
+}
+
+// When the file was last modified, which stands in for the capture date of
+// photos that don't have one
+pub(crate) fn modified_date(path: &str) -> Option<String> {
+	let modified = metadata(path)
+		.and_then(|metadata| metadata.modified())
+		.ok()?;
+
+	Some(
+		DateTime::<Local>::from(modified)
+			.format("%Y-%m-%dT%H:%M:%S%z")
+			.to_string(),
+	)
+}
+
+// Reads the same tags exiftool would give, formatted the same way, without
+// leaving the process
+pub(crate) struct Native;
+
+impl MetadataSource for Native {
+	fn read(_config: &SourceConfig, path: &str) -> Result<Tags, UploadError> {
+		let bytes = read(path).map_err(|err| {
+			debug!("Error reading {}: {:?}", path, err);
+
+			UploadError::UnknownError(Some(format!("Unable to read the photo at {}", path)))
+		})?;
+		let exif = match Reader::new().read_from_container(&mut Cursor::new(&bytes)) {
+			Ok(exif) => Some(exif),
+			// Scans, screenshots, and web exports often have no EXIF data, but can
+			// still have XMP, IPTC, and dimensions
+			Err(::exif::Error::NotFound(_)) => None,
+			Err(err) => {
+				debug!("Error reading EXIF data natively: {:?}", err);
+
+				return Err(UploadError::UnknownError(Some(format!(
+					"Unable to read EXIF data from {}",
+					path
+				))));
+			}
+		};
+		let exif = exif.as_ref();
+		let xmp = xmp::find_packet(&bytes)
+			.and_then(xmp::parse)
+			.unwrap_or_default();
+		let iptc = iptc::parse(&bytes).unwrap_or_default();
+		// The APEX values are only used when the exposure time and f-number
+		// are missing, so convert them to the same units
+		let shutter_speed = number(exif, Tag::ShutterSpeedValue)
+			.and_then(|apex| ShutterSpeed::from_seconds(2f64.powf(-apex)));
+		let aperture = number(exif, Tag::ApertureValue).map(|apex| 2f64.powf(apex / 2.0));
+		let dimensions = jpeg_dimensions(&bytes)
+			.or_else(|| {
+				Some((
+					number(exif, Tag::PixelXDimension)? as u32,
+					number(exif, Tag::PixelYDimension)? as u32,
+				))
+			})
+			.or_else(|| {
+				Some((
+					number(exif, Tag::ImageWidth)? as u32,
+					number(exif, Tag::ImageLength)? as u32,
+				))
+			});
+		let keywords = if xmp.subject.is_empty() {
+			iptc.keywords
+		} else {
+			xmp.subject
+		};
+		let time_created = iptc.time_created;
+		let uint = |tag: Tag| number(exif, tag).map(|value| value as u32);
+		let tags = Tags {
+			make: ascii(exif, Tag::Make),
+			model: ascii(exif, Tag::Model),
+			lens_make: ascii(exif, Tag::LensMake),
+			lens_model: ascii(exif, Tag::LensModel),
+			focal_length_in_35mm_format: uint(Tag::FocalLengthIn35mmFilm)
+				.filter(|focal_length| *focal_length > 0)
+				.map(|focal_length| format!("{} mm", focal_length)),
+			exposure_compensation: number(exif, Tag::ExposureBiasValue).map(exposure_compensation),
+			metering_mode: uint(Tag::MeteringMode).map(metering_mode),
+			flash: uint(Tag::Flash).map(flash),
+			white_balance: uint(Tag::WhiteBalance).map(white_balance),
+			iso: number(exif, Tag::PhotographicSensitivity).map(|iso| format!("{}", iso.round())),
+			exposure_time: rational(exif, Tag::ExposureTime),
+			f_number: number(exif, Tag::FNumber).map(|f_number| f_number.to_string()),
+			shutter_speed_value: shutter_speed.map(|shutter_speed| shutter_speed.to_string()),
+			aperture_value: aperture.map(|aperture| format!("{:.1}", aperture)),
+			focal_length: number(exif, Tag::FocalLength)
+				.map(|focal_length| format!("{:.1} mm", focal_length)),
+			image_width: dimensions.map(|(width, _)| width.to_string()),
+			image_height: dimensions.map(|(_, height)| height.to_string()),
+			orientation: uint(Tag::Orientation)
+				.map(|orientation| orientation.to_string())
+				.or(xmp.orientation),
+			title: xmp.title.or(iptc.object_name),
+			description: xmp
+				.description
+				.or(iptc.caption)
+				.or_else(|| ascii(exif, Tag::ImageDescription)),
+			keywords: non_empty(keywords),
+			hierarchical_subject: non_empty(xmp.hierarchical_subject),
+			rating: xmp.rating,
+			label: xmp.label,
+			gps_latitude: coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S")
+				.or(xmp.gps_latitude),
+			gps_longitude: coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W")
+				.or(xmp.gps_longitude),
+			gps_altitude: altitude(exif).or(xmp.gps_altitude),
+			date_time_created: iptc
+				.date_created
+				.and_then(|date| iptc_date(&date, time_created.as_deref())),
+			date_time_original: ascii(exif, Tag::DateTimeOriginal).and_then(|date| exif_date(&date)),
+			file_modify_date: modified_date(path),
+			offset_time_original: ascii(exif, Tag::OffsetTimeOriginal),
+			gps_date_time: gps_date(exif),
+		};
+
+		debug!("native metadata: {:?}", tags);
+
+		Ok(tags)
+	}
+}
+
+// The EXIF data of a TIFF file, for processed copies to carry, since the image
+// reader only gives it for other formats. Maker notes are left out, since they
+// can point anywhere in the original file
+pub(crate) fn tiff_exif(path: &Path) -> Option<Vec<u8>> {
+	let file = File::open(path).ok()?;
+	let exif = Reader::new()
+		.read_from_container(&mut BufReader::new(file))
+		.map_err(|err| debug!("Error reading the EXIF data in {:?}: {:?}", path, err))
+		.ok()?;
+	let mut writer = Writer::new();
+
+	exif
+		.fields()
+		.filter(|field| field.ifd_num == In::PRIMARY)
+		.filter(|field| match field.tag.context() {
+			Context::Tiff => DESCRIPTIVE_TIFF_TAGS.contains(&field.tag),
+			_ => field.tag != Tag::MakerNote,
+		})
+		.for_each(|field| writer.push_field(field));
+
+	let mut bytes = Cursor::new(Vec::new());
+
+	writer
+		.write(&mut bytes, exif.little_endian())
+		.map_err(|err| debug!("Error copying the EXIF data in {:?}: {:?}", path, err))
+		.ok()?;
+
+	Some(bytes.into_inner())
+}
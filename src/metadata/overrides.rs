@@ -0,0 +1,75 @@
+use crate::metadata::source::{MetadataSource, SourceConfig};
+use crate::metadata::tags::Tags;
+use crate::UploadError;
+use dirs::home_dir;
+use log::debug;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+// Reads hand written tags from a JSON or TOML file, keyed by the photo's file
+// name, for photos whose metadata can't be fixed in the file itself
+pub(crate) struct Overrides;
+
+// Paths in the config can start with `~/` for the home directory
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+	match (path.strip_prefix("~/"), home_dir()) {
+		(Some(rest), Some(home)) => home.join(rest),
+		_ => PathBuf::from(path),
+	}
+}
+
+fn parse(file: &Path, contents: &str) -> Result<HashMap<String, Tags>, String> {
+	let is_json = file
+		.extension()
+		.map(|extension| extension.eq_ignore_ascii_case("json"))
+		.unwrap_or(false);
+
+	if is_json {
+		serde_json::from_str(contents).map_err(|err| err.to_string())
+	} else {
+		toml::from_str(contents).map_err(|err| err.to_string())
+	}
+}
+
+impl MetadataSource for Overrides {
+	fn read(config: &SourceConfig, path: &str) -> Result<Tags, UploadError> {
+		let file = config
+			.path
+			.as_ref()
+			.map(|file| expand_home(file))
+			.ok_or_else(|| {
+				UploadError::MalformedConfig(Some("The overrides metadata source needs a path".into()))
+			})?;
+		let contents = read_to_string(&file).map_err(|err| {
+			debug!("Error reading the overrides file {:?}: {:?}", file, err);
+
+			UploadError::MissingConfig(Some(format!(
+				"Unable to read the metadata overrides at {}",
+				file.display()
+			)))
+		})?;
+		let mut overrides = parse(&file, &contents).map_err(|err| {
+			debug!("Error parsing the overrides file {:?}: {}", file, err);
+
+			UploadError::MalformedConfig(Some(format!(
+				"Unable to parse the metadata overrides at {}",
+				file.display()
+			)))
+		})?;
+		let file_name = Path::new(path)
+			.file_name()
+			.map(|name| name.to_string_lossy().to_string())
+			.unwrap_or_default();
+
+		overrides
+			.remove(&file_name)
+			.or_else(|| overrides.remove(path))
+			.ok_or_else(|| {
+				UploadError::UnknownError(Some(format!(
+					"No metadata overrides were found for {}",
+					path
+				)))
+			})
+	}
+}
@@ -0,0 +1,811 @@
+use crate::metadata::exif::Metadata;
+use crate::metadata::location::Location;
+use crate::metadata::{iptc, xmp};
+use crate::UploadError;
+use log::debug;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::fs::{read, write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const DEFAULT_ROUND_KM: f64 = 10.0;
+
+// TIFF tags that point to other directories, hold identifying details, or
+// say which way up the photo goes
+const ORIENTATION: u16 = 0x0112;
+const EXIF_IFD: u16 = 0x8769;
+const GPS_IFD: u16 = 0x8825;
+// TIFF files keep their IPTC block in a tag, rather than a JPEG segment
+const IPTC_NAA: u16 = 0x83BB;
+const CAMERA_SERIAL_NUMBER: u16 = 0xC62F;
+const OWNER_TAGS: [u16; 4] = [
+	0x927C, // MakerNote, which has the body's serial number for most brands
+	0xA430, // CameraOwnerName
+	0xA431, // BodySerialNumber
+	0xA435, // LensSerialNumber
+];
+
+// GPS tags
+const LATITUDE_REF: u16 = 1;
+const LATITUDE: u16 = 2;
+const LONGITUDE_REF: u16 = 3;
+const LONGITUDE: u16 = 4;
+// The altitude, where the camera was pointed, and the area's name can all
+// narrow a rounded location back down
+const PRECISE_GPS_TAGS: [u16; 10] = [5, 6, 20, 21, 22, 23, 24, 25, 26, 28];
+
+// XMP properties, as the editors that write them name them
+const XMP_GPS: &str = r"exif:GPS\w*";
+const XMP_SUBLOCATION: &str = r"Iptc4xmpCore:Location";
+const XMP_PLACE: &str =
+	r"photoshop:City|photoshop:State|photoshop:Country|Iptc4xmpCore:CountryCode";
+// IPTC datasets, from the most to the least precise
+const IPTC_SUBLOCATION: [u8; 1] = [iptc::SUBLOCATION];
+const IPTC_PLACE: [u8; 5] = [
+	iptc::SUBLOCATION,
+	iptc::CITY,
+	iptc::PROVINCE,
+	iptc::COUNTRY_CODE,
+	iptc::COUNTRY,
+];
+
+const XMP_OWNER: &str = r"aux:SerialNumber|aux:LensSerialNumber|aux:OwnerName|exifEX:BodySerialNumber|exifEX:LensSerialNumber|exifEX:CameraOwnerName";
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LocationPolicy {
+	// Publish the location as it is
+	#[default]
+	Keep,
+	// Only publish roughly where the photo was taken
+	Round,
+	// Don't publish the location at all
+	Strip,
+}
+
+// What a destination is allowed to know about where a photo was taken, and
+// who took it
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct PrivacyConfig {
+	#[serde(default)]
+	pub(crate) location: LocationPolicy,
+	// How coarse rounded locations are, in kilometers
+	pub(crate) round_km: Option<f64>,
+	// Remove the camera owner's name, and the camera's and lens's serial numbers
+	#[serde(default)]
+	pub(crate) strip_owner: bool,
+}
+
+impl PrivacyConfig {
+	pub(crate) fn round_km(&self) -> f64 {
+		self.round_km.unwrap_or(DEFAULT_ROUND_KM)
+	}
+
+	fn changes_file(&self) -> bool {
+		self.location != LocationPolicy::Keep || self.strip_owner
+	}
+
+	// The IPTC place names that are removed, which matches what's removed from
+	// the XMP data
+	fn iptc_places(&self) -> &'static [u8] {
+		match self.location {
+			LocationPolicy::Keep => &[],
+			LocationPolicy::Round => &IPTC_SUBLOCATION,
+			LocationPolicy::Strip => &IPTC_PLACE,
+		}
+	}
+}
+
+// The location a destination should be sent for a photo
+pub(crate) fn location(config: Option<&PrivacyConfig>, metadata: &Metadata) -> Option<Location> {
+	let location = metadata.location.as_ref()?;
+
+	match config.map(|config| (config.location, config.round_km())) {
+		Some((LocationPolicy::Strip, _)) => None,
+		Some((LocationPolicy::Round, km)) => Some(location.rounded(km)),
+		_ => Some(location.clone()),
+	}
+}
+
+// Remove private details from EXIF data that's being copied into a processed
+// photo, which is a TIFF structure on its own
+pub(crate) fn sanitize_exif(
+	config: Option<&PrivacyConfig>,
+	exif: &mut [u8],
+) -> Result<(), UploadError> {
+	let config = match config {
+		Some(config) if config.changes_file() => config,
+		_ => return Ok(()),
+	};
+
+	Tiff::new(exif)
+		.and_then(|mut tiff| sanitize_tiff(&mut tiff, config))
+		.ok_or_else(|| {
+			UploadError::MalformedMetadata(Some(
+				"Unable to remove private metadata from the photo's EXIF data".into(),
+			))
+		})
+}
+
+// Processed photos are turned upright, so the orientation they're copied
+// with has to say so, or they'd be turned again
+pub(crate) fn upright_exif(exif: &mut [u8]) -> Option<()> {
+	let mut tiff = Tiff::new(exif)?;
+	let ifd0 = tiff.entries(tiff.u32(4)? as usize)?;
+	let orientation = ifd0
+		.iter()
+		.find(|entry| entry.tag == ORIENTATION && entry.kind == 3)?;
+
+	tiff.set_u16(orientation.offset, 1);
+
+	Some(())
+}
+
+// The photo to upload, which is a sanitized copy when the destination's
+// privacy settings call for one. The copy is deleted when this is dropped
+pub(crate) struct SanitizedPhoto {
+	path: PathBuf,
+	_directory: Option<TempDir>,
+}
+
+impl SanitizedPhoto {
+	pub(crate) fn path(&self) -> &Path {
+		&self.path
+	}
+}
+
+#[derive(Clone, Copy)]
+enum ByteOrder {
+	Little,
+	Big,
+}
+
+struct Entry {
+	tag: u16,
+	kind: u16,
+	count: u32,
+	// Where the value is, from the start of the TIFF header
+	offset: usize,
+	size: usize,
+}
+
+// The EXIF data of a photo, edited in place so nothing else in the file moves
+struct Tiff<'a> {
+	bytes: &'a mut [u8],
+	order: ByteOrder,
+}
+
+fn type_size(kind: u16) -> usize {
+	match kind {
+		3 | 8 => 2,
+		4 | 9 | 11 | 13 => 4,
+		5 | 10 | 12 => 8,
+		_ => 1,
+	}
+}
+
+impl<'a> Tiff<'a> {
+	fn new(bytes: &'a mut [u8]) -> Option<Tiff<'a>> {
+		let order = match bytes.get(0..4)? {
+			[b'I', b'I', 42, 0] => ByteOrder::Little,
+			[b'M', b'M', 0, 42] => ByteOrder::Big,
+			_ => return None,
+		};
+
+		Some(Tiff { bytes, order })
+	}
+
+	fn u16(&self, offset: usize) -> Option<u16> {
+		let bytes = [*self.bytes.get(offset)?, *self.bytes.get(offset + 1)?];
+
+		Some(match self.order {
+			ByteOrder::Little => u16::from_le_bytes(bytes),
+			ByteOrder::Big => u16::from_be_bytes(bytes),
+		})
+	}
+
+	fn u32(&self, offset: usize) -> Option<u32> {
+		let bytes: [u8; 4] = self.bytes.get(offset..offset + 4)?.try_into().ok()?;
+
+		Some(match self.order {
+			ByteOrder::Little => u32::from_le_bytes(bytes),
+			ByteOrder::Big => u32::from_be_bytes(bytes),
+		})
+	}
+
+	fn set_u16(&mut self, offset: usize, value: u16) {
+		let bytes = match self.order {
+			ByteOrder::Little => value.to_le_bytes(),
+			ByteOrder::Big => value.to_be_bytes(),
+		};
+
+		self.bytes[offset..offset + 2].copy_from_slice(&bytes);
+	}
+
+	fn set_u32(&mut self, offset: usize, value: u32) {
+		let bytes = match self.order {
+			ByteOrder::Little => value.to_le_bytes(),
+			ByteOrder::Big => value.to_be_bytes(),
+		};
+
+		self.bytes[offset..offset + 4].copy_from_slice(&bytes);
+	}
+
+	fn entries(&self, ifd: usize) -> Option<Vec<Entry>> {
+		let count = usize::from(self.u16(ifd)?);
+
+		(0..count)
+			.map(|index| {
+				let position = ifd + 2 + index * 12;
+				let kind = self.u16(position + 2)?;
+				let count = self.u32(position + 4)?;
+				let size = type_size(kind).checked_mul(count as usize)?;
+				let offset = if size <= 4 {
+					position + 8
+				} else {
+					self.u32(position + 8)? as usize
+				};
+
+				self.bytes.get(offset..offset.checked_add(size)?)?;
+
+				Some(Entry {
+					tag: self.u16(position)?,
+					kind,
+					count,
+					offset,
+					size,
+				})
+			})
+			.collect()
+	}
+
+	fn clear(&mut self, entry: &Entry) {
+		self.bytes[entry.offset..entry.offset + entry.size].fill(0);
+	}
+
+	// Degrees, minutes, and seconds as signed decimal degrees
+	fn coordinate(&self, entry: &Entry, reference: Option<&Entry>, negative: u8) -> Option<f64> {
+		if entry.kind != 5 || entry.count != 3 {
+			return None;
+		}
+
+		let degrees: f64 = (0..3)
+			.map(|index| {
+				let offset = entry.offset + index * 8;
+				let denominator = f64::from(self.u32(offset + 4)?);
+
+				Some(f64::from(self.u32(offset)?) / denominator / 60f64.powi(index as i32))
+			})
+			.sum::<Option<f64>>()?;
+		let sign = match reference.map(|reference| self.bytes[reference.offset]) {
+			Some(hemisphere) if hemisphere == negative => -1.0,
+			_ => 1.0,
+		};
+
+		Some(sign * degrees).filter(|degrees| degrees.is_finite())
+	}
+
+	fn set_coordinate(
+		&mut self,
+		entry: &Entry,
+		reference: Option<&Entry>,
+		degrees: f64,
+		hemispheres: (u8, u8),
+	) {
+		let absolute = degrees.abs();
+		let whole = absolute.trunc();
+		let minutes = ((absolute - whole) * 60.0).trunc();
+		let hundredths_of_seconds = ((absolute - whole) * 3600.0 - minutes * 60.0) * 100.0;
+
+		for (index, (numerator, denominator)) in [
+			(whole as u32, 1),
+			(minutes as u32, 1),
+			(hundredths_of_seconds.round() as u32, 100),
+		]
+		.iter()
+		.enumerate()
+		{
+			self.set_u32(entry.offset + index * 8, *numerator);
+			self.set_u32(entry.offset + index * 8 + 4, *denominator);
+		}
+
+		if let Some(reference) = reference {
+			self.bytes[reference.offset] = if degrees < 0.0 {
+				hemispheres.1
+			} else {
+				hemispheres.0
+			};
+		}
+	}
+}
+
+// Where the contents of the first JPEG segment with `marker` that starts with
+// `signature` are, after the signature
+fn jpeg_segment(bytes: &[u8], marker: u8, signature: &[u8]) -> Option<Range<usize>> {
+	if !bytes.starts_with(&[0xFF, 0xD8]) {
+		return None;
+	}
+
+	let mut offset = 2;
+
+	while bytes.get(offset) == Some(&0xFF) {
+		// Any number of fill bytes can come before a marker
+		while bytes.get(offset + 1) == Some(&0xFF) {
+			offset += 1;
+		}
+
+		let found = *bytes.get(offset + 1)?;
+		let length = usize::from(u16::from_be_bytes([
+			*bytes.get(offset + 2)?,
+			*bytes.get(offset + 3)?,
+		]));
+		let start = offset + 4 + signature.len();
+		let end = offset + 2 + length;
+
+		// The image data starts at the start of scan marker
+		if found == 0xDA {
+			return None;
+		}
+
+		if found == marker && start <= end && bytes.get(offset + 4..start) == Some(signature) {
+			return bytes.get(start..end).map(|_| start..end);
+		}
+
+		offset = end;
+	}
+
+	None
+}
+
+// Where the TIFF header is, for TIFF based files like most RAW formats, and
+// for JPEGs with EXIF data
+fn tiff_start(bytes: &[u8]) -> Option<usize> {
+	if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+		return Some(0);
+	}
+
+	jpeg_segment(bytes, 0xE1, b"Exif\0\0").map(|segment| segment.start)
+}
+
+fn sanitize_tiff(tiff: &mut Tiff, config: &PrivacyConfig) -> Option<()> {
+	let ifd0 = tiff.entries(tiff.u32(4)? as usize)?;
+	let mut pointers = Vec::new();
+
+	for entry in &ifd0 {
+		match entry.tag {
+			EXIF_IFD | GPS_IFD => pointers.push((entry.tag, tiff.u32(entry.offset)? as usize)),
+			CAMERA_SERIAL_NUMBER if config.strip_owner => tiff.clear(entry),
+			IPTC_NAA => iptc::blank(
+				&mut tiff.bytes[entry.offset..entry.offset + entry.size],
+				config.iptc_places(),
+			),
+			_ => {}
+		}
+	}
+
+	for (tag, ifd) in pointers {
+		let entries = tiff.entries(ifd)?;
+
+		match tag {
+			EXIF_IFD if config.strip_owner => entries
+				.iter()
+				.filter(|entry| OWNER_TAGS.contains(&entry.tag))
+				.for_each(|entry| tiff.clear(entry)),
+			GPS_IFD if config.location == LocationPolicy::Strip => {
+				// Leave an empty directory behind, so the pointer to it still works
+				entries.iter().for_each(|entry| tiff.clear(entry));
+				tiff
+					.bytes
+					.get_mut(ifd..ifd + 2 + entries.len() * 12 + 4)?
+					.fill(0);
+			}
+			GPS_IFD if config.location == LocationPolicy::Round => {
+				let find = |tag: u16| entries.iter().find(|entry| entry.tag == tag);
+
+				entries
+					.iter()
+					.filter(|entry| PRECISE_GPS_TAGS.contains(&entry.tag))
+					.for_each(|entry| tiff.clear(entry));
+
+				if let (Some(latitude), Some(longitude)) = (find(LATITUDE), find(LONGITUDE)) {
+					let location = Location {
+						latitude: tiff.coordinate(latitude, find(LATITUDE_REF), b'S')?,
+						longitude: tiff.coordinate(longitude, find(LONGITUDE_REF), b'W')?,
+						altitude: None,
+						place: None,
+					}
+					.rounded(config.round_km());
+
+					tiff.set_coordinate(
+						latitude,
+						find(LATITUDE_REF),
+						location.latitude,
+						(b'N', b'S'),
+					);
+					tiff.set_coordinate(
+						longitude,
+						find(LONGITUDE_REF),
+						location.longitude,
+						(b'E', b'W'),
+					);
+				}
+			}
+			_ => {}
+		}
+	}
+
+	Some(())
+}
+
+// Blank out properties in an XMP packet with spaces, so its length doesn't
+// change. Properties can be attributes or elements
+fn sanitize_xmp(packet: &mut [u8], config: &PrivacyConfig) -> Option<()> {
+	let mut names = Vec::new();
+
+	match config.location {
+		LocationPolicy::Keep => {}
+		LocationPolicy::Round => names.extend([XMP_GPS, XMP_SUBLOCATION]),
+		LocationPolicy::Strip => names.extend([XMP_GPS, XMP_SUBLOCATION, XMP_PLACE]),
+	}
+
+	if config.strip_owner {
+		names.push(XMP_OWNER);
+	}
+
+	if names.is_empty() {
+		return Some(());
+	}
+
+	let names = names.join("|");
+	let properties = Regex::new(&format!(
+		r#"\s(?:{names})="[^"]*"|<(?:{names})>[^<]*</(?:{names})>"#,
+		names = names
+	))
+	.ok()?;
+	let text = std::str::from_utf8(packet).ok()?;
+	let sanitized = properties
+		.replace_all(text, |captures: &Captures| " ".repeat(captures[0].len()))
+		.into_owned();
+
+	packet.copy_from_slice(sanitized.as_bytes());
+
+	Some(())
+}
+
+// Copy the photo without the details the destination isn't allowed to have.
+// Photos are only copied when something needs to be removed, and the copy
+// keeps the original file name, since that's what's uploaded with it
+pub(crate) fn sanitize(
+	config: Option<&PrivacyConfig>,
+	path: &str,
+) -> Result<SanitizedPhoto, UploadError> {
+	let config = match config {
+		Some(config) if config.changes_file() => config,
+		_ => {
+			return Ok(SanitizedPhoto {
+				path: PathBuf::from(path),
+				_directory: None,
+			})
+		}
+	};
+	let unsanitizable = || {
+		UploadError::MalformedMetadata(Some(format!(
+			"Unable to remove private metadata from {}",
+			path
+		)))
+	};
+	let mut bytes = read(path).map_err(|err| {
+		debug!("Error reading {}: {:?}", path, err);
+
+		UploadError::UnknownError(Some(format!("Unable to read the photo at {}", path)))
+	})?;
+
+	match tiff_start(&bytes) {
+		Some(start) => {
+			let mut tiff = Tiff::new(&mut bytes[start..]).ok_or_else(unsanitizable)?;
+
+			sanitize_tiff(&mut tiff, config).ok_or_else(unsanitizable)?;
+		}
+		// JPEGs without EXIF data are fine, but other files could be hiding it
+		// somewhere this doesn't know to look
+		None if !bytes.starts_with(&[0xFF, 0xD8]) => {
+			return Err(UploadError::MalformedMetadata(Some(format!(
+				"Private metadata can only be removed from JPEG and TIFF based photos, so {} wasn't uploaded",
+				path
+			))))
+		}
+		None => {}
+	}
+
+	if let Some(segment) = jpeg_segment(&bytes, 0xED, b"Photoshop 3.0\0") {
+		iptc::blank(&mut bytes[segment], config.iptc_places());
+	}
+
+	// Some files have more than one packet, like JPEGs with extended XMP
+	let mut start = 0;
+
+	while let Some(range) = xmp::packet_range(&bytes[start..]) {
+		let range = start + range.start..start + range.end;
+
+		sanitize_xmp(&mut bytes[range.clone()], config).ok_or_else(unsanitizable)?;
+		start = range.end;
+	}
+
+	let directory = tempfile::Builder::new()
+		.prefix("phupload-")
+		.tempdir()
+		.map_err(|err| {
+			debug!("Error creating a temporary directory: {:?}", err);
+
+			UploadError::UnknownError(Some(
+				"Unable to create a temporary copy of the photo".into(),
+			))
+		})?;
+	let copy = directory
+		.path()
+		.join(Path::new(path).file_name().unwrap_or_default());
+
+	write(&copy, bytes).map_err(|err| {
+		debug!("Error writing the sanitized copy {:?}: {:?}", copy, err);
+
+		UploadError::UnknownError(Some(
+			"Unable to create a temporary copy of the photo".into(),
+		))
+	})?;
+
+	debug!("Uploading a sanitized copy of {} from {:?}", path, copy);
+
+	Ok(SanitizedPhoto {
+		path: copy,
+		_directory: Some(directory),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const EXIF_AT: u32 = 50;
+	const MAKER_NOTE_AT: usize = 60;
+	const OWNER_AT: usize = 80;
+	const GPS_AT: u32 = 86;
+	const LATITUDE_AT: u32 = 152;
+	const LONGITUDE_AT: u32 = 176;
+	const ALTITUDE_AT: u32 = 200;
+
+	// A TIFF header with an orientation, a maker note, a camera owner, and a GPS
+	// position of 45° 30' 36" N, 122° 40' 12" W at 100 meters
+	fn tiff(order: ByteOrder) -> Vec<u8> {
+		let u16_bytes = |value: u16| match order {
+			ByteOrder::Little => value.to_le_bytes().to_vec(),
+			ByteOrder::Big => value.to_be_bytes().to_vec(),
+		};
+		let u32_bytes = |value: u32| match order {
+			ByteOrder::Little => value.to_le_bytes().to_vec(),
+			ByteOrder::Big => value.to_be_bytes().to_vec(),
+		};
+		let entry = |tag: u16, kind: u16, count: u32, value: Vec<u8>| {
+			let mut value = value;
+
+			value.resize(4, 0);
+
+			[u16_bytes(tag), u16_bytes(kind), u32_bytes(count), value].concat()
+		};
+		let ifd = |entries: Vec<Vec<u8>>| {
+			[
+				u16_bytes(entries.len() as u16),
+				entries.concat(),
+				u32_bytes(0),
+			]
+			.concat()
+		};
+		let rationals = |values: &[(u32, u32)]| {
+			values
+				.iter()
+				.flat_map(|(numerator, denominator)| [u32_bytes(*numerator), u32_bytes(*denominator)])
+				.collect::<Vec<Vec<u8>>>()
+				.concat()
+		};
+		let header = match order {
+			ByteOrder::Little => b"II*\0".to_vec(),
+			ByteOrder::Big => b"MM\0*".to_vec(),
+		};
+
+		[
+			header,
+			u32_bytes(8),
+			ifd(vec![
+				entry(ORIENTATION, 3, 1, u16_bytes(6)),
+				entry(EXIF_IFD, 4, 1, u32_bytes(EXIF_AT)),
+				entry(GPS_IFD, 4, 1, u32_bytes(GPS_AT)),
+			]),
+			ifd(vec![
+				entry(OWNER_TAGS[0], 7, 4, b"SN42".to_vec()),
+				entry(OWNER_TAGS[1], 2, 6, u32_bytes(OWNER_AT as u32)),
+			]),
+			b"Alice\0".to_vec(),
+			ifd(vec![
+				entry(LATITUDE_REF, 2, 2, b"N\0".to_vec()),
+				entry(LATITUDE, 5, 3, u32_bytes(LATITUDE_AT)),
+				entry(LONGITUDE_REF, 2, 2, b"W\0".to_vec()),
+				entry(LONGITUDE, 5, 3, u32_bytes(LONGITUDE_AT)),
+				entry(6, 5, 1, u32_bytes(ALTITUDE_AT)),
+			]),
+			rationals(&[(45, 1), (30, 1), (36, 1)]),
+			rationals(&[(122, 1), (40, 1), (12, 1)]),
+			rationals(&[(100, 1)]),
+		]
+		.concat()
+	}
+
+	fn sanitized(order: ByteOrder, config: PrivacyConfig) -> Vec<u8> {
+		let mut bytes = tiff(order);
+
+		sanitize_tiff(&mut Tiff::new(&mut bytes).unwrap(), &config).unwrap();
+
+		bytes
+	}
+
+	// The latitude, longitude, and altitude left in the GPS directory
+	fn position(bytes: &mut [u8]) -> (Option<f64>, Option<f64>, Option<u32>) {
+		let tiff = Tiff::new(bytes).unwrap();
+		let entries = tiff.entries(GPS_AT as usize).unwrap();
+		let find = |tag: u16| entries.iter().find(|entry| entry.tag == tag);
+
+		(
+			find(LATITUDE).and_then(|entry| tiff.coordinate(entry, find(LATITUDE_REF), b'S')),
+			find(LONGITUDE).and_then(|entry| tiff.coordinate(entry, find(LONGITUDE_REF), b'W')),
+			find(6).and_then(|entry| tiff.u32(entry.offset)),
+		)
+	}
+
+	#[test]
+	fn finds_the_tiff_header() {
+		assert_eq!(tiff_start(&tiff(ByteOrder::Little)), Some(0));
+		assert_eq!(tiff_start(&tiff(ByteOrder::Big)), Some(0));
+
+		// An APP0 segment, then fill bytes before the APP1 marker
+		let mut jpeg = vec![
+			0xFF, 0xD8, 0xFF, 0xE0, 0, 4, 0, 0, 0xFF, 0xFF, 0xFF, 0xE1, 0, 8,
+		];
+
+		jpeg.extend(b"Exif\0\0");
+		jpeg.extend(b"II*\0");
+
+		assert_eq!(tiff_start(&jpeg), Some(jpeg.len() - 4));
+		assert_eq!(tiff_start(&[0xFF, 0xD8, 0xFF, 0xDA, 0, 2]), None);
+		assert_eq!(tiff_start(b"\x89PNG"), None);
+	}
+
+	#[test]
+	fn strips_the_location() {
+		for order in [ByteOrder::Little, ByteOrder::Big] {
+			let mut bytes = sanitized(
+				order,
+				PrivacyConfig {
+					location: LocationPolicy::Strip,
+					..Default::default()
+				},
+			);
+			let gps = GPS_AT as usize;
+
+			assert!(bytes[gps..].iter().all(|byte| *byte == 0));
+			assert_eq!(&bytes[OWNER_AT..OWNER_AT + 5], b"Alice");
+			assert_eq!(position(&mut bytes), (None, None, None));
+		}
+	}
+
+	#[test]
+	fn rounds_the_location() {
+		for order in [ByteOrder::Little, ByteOrder::Big] {
+			let config = PrivacyConfig {
+				location: LocationPolicy::Round,
+				..Default::default()
+			};
+			let expected = Location {
+				latitude: 45.51,
+				longitude: -122.67,
+				altitude: None,
+				place: None,
+			}
+			.rounded(config.round_km());
+			let mut bytes = sanitized(order, config);
+			let (latitude, longitude, altitude) = position(&mut bytes);
+
+			assert!((latitude.unwrap() - expected.latitude).abs() < 0.0001);
+			assert!((longitude.unwrap() - expected.longitude).abs() < 0.0001);
+			assert_ne!(latitude, Some(45.51));
+			assert_eq!(altitude, Some(0));
+			assert_eq!(&bytes[OWNER_AT..OWNER_AT + 5], b"Alice");
+		}
+	}
+
+	#[test]
+	fn strips_the_owner() {
+		for order in [ByteOrder::Little, ByteOrder::Big] {
+			let mut bytes = sanitized(
+				order,
+				PrivacyConfig {
+					strip_owner: true,
+					..Default::default()
+				},
+			);
+			let (latitude, longitude, altitude) = position(&mut bytes);
+
+			assert_eq!(&bytes[OWNER_AT..OWNER_AT + 6], &[0; 6]);
+			assert!((latitude.unwrap() - 45.51).abs() < 0.0001);
+			assert!((longitude.unwrap() + 122.67).abs() < 0.0001);
+			assert_eq!(altitude, Some(100));
+		}
+	}
+
+	#[test]
+	fn strips_the_maker_note() {
+		for order in [ByteOrder::Little, ByteOrder::Big] {
+			let kept = sanitized(
+				order,
+				PrivacyConfig {
+					location: LocationPolicy::Strip,
+					..Default::default()
+				},
+			);
+			let stripped = sanitized(
+				order,
+				PrivacyConfig {
+					strip_owner: true,
+					..Default::default()
+				},
+			);
+
+			assert_eq!(&kept[MAKER_NOTE_AT..MAKER_NOTE_AT + 4], b"SN42");
+			assert_eq!(&stripped[MAKER_NOTE_AT..MAKER_NOTE_AT + 4], &[0; 4]);
+		}
+	}
+
+	#[test]
+	fn strips_iptc_place_names() {
+		let dataset = |number: u8, value: &[u8]| {
+			[
+				vec![0x1C, 2, number],
+				(value.len() as u16).to_be_bytes().to_vec(),
+				value.to_vec(),
+			]
+			.concat()
+		};
+		let record = [
+			dataset(0, &[0, 2]),
+			dataset(iptc::SUBLOCATION, b"Home"),
+			dataset(iptc::CITY, b"Portland"),
+			dataset(5, b"Title"),
+		]
+		.concat();
+		let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xED];
+
+		jpeg.extend(((2 + 14 + record.len()) as u16).to_be_bytes());
+		jpeg.extend(b"Photoshop 3.0\0");
+		jpeg.extend(&record);
+
+		for (policy, keeps_city) in [
+			(LocationPolicy::Round, true),
+			(LocationPolicy::Strip, false),
+		] {
+			let mut bytes = jpeg.clone();
+			let config = PrivacyConfig {
+				location: policy,
+				..Default::default()
+			};
+			let segment = jpeg_segment(&bytes, 0xED, b"Photoshop 3.0\0").unwrap();
+
+			iptc::blank(&mut bytes[segment], config.iptc_places());
+
+			let text = String::from_utf8_lossy(&bytes);
+
+			assert!(!text.contains("Home"));
+			assert_eq!(text.contains("Portland"), keeps_city);
+			assert!(text.contains("Title"));
+		}
+	}
+}
@@ -0,0 +1,65 @@
+use crate::metadata::source::{MetadataSource, SourceConfig};
+use crate::metadata::tags::{non_empty, Tags};
+use crate::metadata::xmp;
+use crate::UploadError;
+use log::debug;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+// Reads the `.xmp` file editors like Lightroom and darktable keep next to a photo
+pub(crate) struct Sidecar;
+
+// Sidecars are either named after the photo without its extension
+// (`photo.xmp`), or with it (`photo.jpg.xmp`)
+fn sidecar_path(path: &str) -> Option<PathBuf> {
+	let photo = Path::new(path);
+	let candidates = [
+		photo.with_extension("xmp"),
+		photo.with_extension("XMP"),
+		PathBuf::from(format!("{}.xmp", path)),
+		PathBuf::from(format!("{}.XMP", path)),
+	];
+
+	candidates
+		.iter()
+		.find(|candidate| candidate.is_file())
+		.cloned()
+}
+
+impl MetadataSource for Sidecar {
+	fn read(_config: &SourceConfig, path: &str) -> Result<Tags, UploadError> {
+		let sidecar = sidecar_path(path).ok_or_else(|| {
+			UploadError::UnknownError(Some(format!("No XMP sidecar was found for {}", path)))
+		})?;
+		let contents = read_to_string(&sidecar).map_err(|err| {
+			debug!("Error reading the sidecar {:?}: {:?}", sidecar, err);
+
+			UploadError::UnknownError(Some(format!(
+				"Unable to read the XMP sidecar at {}",
+				sidecar.display()
+			)))
+		})?;
+		let xmp = xmp::find_packet(contents.as_bytes())
+			.and_then(xmp::parse)
+			.ok_or_else(|| {
+				UploadError::UnknownError(Some(format!(
+					"Unable to parse the XMP sidecar at {}",
+					sidecar.display()
+				)))
+			})?;
+
+		Ok(Tags {
+			title: xmp.title,
+			description: xmp.description,
+			keywords: non_empty(xmp.subject),
+			hierarchical_subject: non_empty(xmp.hierarchical_subject),
+			rating: xmp.rating,
+			label: xmp.label,
+			gps_latitude: xmp.gps_latitude,
+			gps_longitude: xmp.gps_longitude,
+			gps_altitude: xmp.gps_altitude,
+			orientation: xmp.orientation,
+			..Tags::default()
+		})
+	}
+}
@@ -0,0 +1,142 @@
+use crate::metadata::exif::Exiftool;
+#[cfg(feature = "native-metadata")]
+use crate::metadata::native::Native;
+use crate::metadata::overrides::Overrides;
+use crate::metadata::sidecar::Sidecar;
+use crate::metadata::tags::Tags;
+use crate::UploadError;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+pub(crate) trait MetadataSource {
+	// Read whatever tags the source has for a photo
+	fn read(config: &SourceConfig, path: &str) -> Result<Tags, UploadError>;
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SourceKind {
+	Exiftool,
+	Native,
+	Sidecar,
+	Overrides,
+}
+
+// How a source's tags are combined with the ones read before it
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MergeMode {
+	// Only fill in tags earlier sources didn't have
+	#[default]
+	Fill,
+	// Replace tags earlier sources had
+	Override,
+	// Only read from this source if every earlier one failed
+	Fallback,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct SourceConfig {
+	pub(crate) kind: SourceKind,
+	#[serde(default)]
+	pub(crate) mode: MergeMode,
+	// The file to read, for sources that need one
+	pub(crate) path: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct MetadataConfig {
+	pub(crate) source: Vec<SourceConfig>,
+}
+
+impl SourceConfig {
+	fn new(kind: SourceKind, mode: MergeMode) -> SourceConfig {
+		SourceConfig {
+			kind,
+			mode,
+			path: None,
+		}
+	}
+
+	// A short name for the source, used when reporting on it
+	pub(crate) fn name(&self) -> String {
+		let kind = format!("{:?}", self.kind).to_lowercase();
+
+		match &self.path {
+			Some(path) => format!("{}:{}", kind, path),
+			None => kind,
+		}
+	}
+}
+
+// Without any sources configured, photos are read natively when that's built
+// in, and with exiftool when it isn't or the native reader fails. A sidecar
+// next to the photo takes precedence over both
+fn default_sources() -> Vec<SourceConfig> {
+	let mut sources = if cfg!(feature = "native-metadata") {
+		vec![
+			SourceConfig::new(SourceKind::Native, MergeMode::Fill),
+			SourceConfig::new(SourceKind::Exiftool, MergeMode::Fallback),
+		]
+	} else {
+		vec![SourceConfig::new(SourceKind::Exiftool, MergeMode::Fill)]
+	};
+
+	sources.push(SourceConfig::new(SourceKind::Sidecar, MergeMode::Override));
+	sources
+}
+
+fn read_from(source: &SourceConfig, path: &str) -> Result<Tags, UploadError> {
+	match source.kind {
+		SourceKind::Exiftool => Exiftool::read(source, path),
+		#[cfg(feature = "native-metadata")]
+		SourceKind::Native => Native::read(source, path),
+		#[cfg(not(feature = "native-metadata"))]
+		SourceKind::Native => Err(UploadError::MalformedConfig(Some(
+			"The native metadata source needs phupload to be built with the native-metadata feature"
+				.into(),
+		))),
+		SourceKind::Sidecar => Sidecar::read(source, path),
+		SourceKind::Overrides => Overrides::read(source, path),
+	}
+}
+
+// Run a photo through each source in turn, merging their tags. A source that
+// fails is skipped, and the photo only fails if every source did
+pub(crate) fn read_sources(
+	config: Option<&MetadataConfig>,
+	path: &str,
+) -> Result<Tags, UploadError> {
+	let sources = config
+		.map(|config| config.source.clone())
+		.unwrap_or_else(default_sources);
+	let mut tags: Option<Tags> = None;
+	let mut last_error: Option<UploadError> = None;
+
+	for source in &sources {
+		if source.mode == MergeMode::Fallback && tags.is_some() {
+			continue;
+		}
+
+		let source_tags = match read_from(source, path) {
+			Ok(source_tags) => source_tags,
+			Err(err) => {
+				debug!("Error reading {} from {}: {}", path, source.name(), err);
+
+				last_error = Some(err);
+				continue;
+			}
+		};
+		tags
+			.get_or_insert_with(Tags::default)
+			.merge(source_tags, source.mode);
+	}
+
+	match (tags, last_error) {
+		(Some(tags), _) => Ok(tags),
+		(None, Some(err)) => Err(err),
+		(None, None) => Err(UploadError::MalformedConfig(Some(
+			"No metadata sources are configured".into(),
+		))),
+	}
+}
@@ -0,0 +1,159 @@
+use crate::metadata::exif::Metadata;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Added to every photo's tags unless `default_tags` says otherwise
+const DEFAULT_TAGS: [&str; 1] = ["upload"];
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TagCase {
+	// Leave tags as they were written
+	#[default]
+	Keep,
+	Lower,
+	Title,
+}
+
+// How keywords are turned into tags. `[tags]` applies to every destination,
+// and `tags` under a destination adds rules that only apply there
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct TagsConfig {
+	// Keywords to replace with another tag, like `nyc = "New York"`. Keywords
+	// are matched regardless of case
+	#[serde(default)]
+	pub(crate) aliases: BTreeMap<String, String>,
+	// Keywords that are never published, regardless of case
+	#[serde(default)]
+	pub(crate) blocklist: Vec<String>,
+	#[serde(default)]
+	pub(crate) case: TagCase,
+	// Tags added to every photo
+	pub(crate) default_tags: Option<Vec<String>>,
+}
+
+// Each word with its first letter uppercased and the rest left alone, so
+// `iPhone` and `St. Louis` are kept as they are
+fn title_case(tag: &str) -> String {
+	tag
+		.split(' ')
+		.map(|word| {
+			let mut chars = word.chars();
+
+			match chars.next() {
+				Some(first) => first.to_uppercase().chain(chars).collect(),
+				None => String::new(),
+			}
+		})
+		.collect::<Vec<String>>()
+		.join(" ")
+}
+
+fn contains(tags: &[String], tag: &str) -> bool {
+	tags
+		.iter()
+		.any(|existing| existing.eq_ignore_ascii_case(tag))
+}
+
+impl TagsConfig {
+	fn alias<'a>(&'a self, tag: &'a str) -> &'a str {
+		self
+			.aliases
+			.iter()
+			.find(|(keyword, _)| keyword.eq_ignore_ascii_case(tag))
+			.map(|(_, alias)| alias.as_str())
+			.unwrap_or(tag)
+	}
+
+	fn blocked(&self, tag: &str) -> bool {
+		contains(&self.blocklist, tag)
+	}
+
+	// Alias, filter, and recase the tags, without repeating any, then add the
+	// default tags. `defaults` is used when `default_tags` isn't set
+	pub(crate) fn apply(&self, tags: &[String], defaults: &[&str]) -> Vec<String> {
+		let mut result: Vec<String> = Vec::new();
+
+		for tag in tags {
+			let tag = tag.trim();
+			let alias = self.alias(tag).trim();
+
+			if alias.is_empty() || self.blocked(tag) || self.blocked(alias) {
+				continue;
+			}
+
+			let alias = match self.case {
+				TagCase::Keep => alias.to_string(),
+				TagCase::Lower => alias.to_lowercase(),
+				TagCase::Title => title_case(alias),
+			};
+
+			if !contains(&result, &alias) {
+				result.push(alias);
+			}
+		}
+
+		let defaults = match &self.default_tags {
+			Some(tags) => tags.iter().map(String::as_str).collect(),
+			None => defaults.to_vec(),
+		};
+
+		for tag in defaults {
+			if !contains(&result, tag) {
+				result.push(tag.into());
+			}
+		}
+
+		result
+	}
+}
+
+// The tags for every destination, from the photo's keywords
+pub(crate) fn photo_tags(config: Option<&TagsConfig>, keywords: &[String]) -> Vec<String> {
+	match config {
+		Some(config) => config.apply(keywords, &DEFAULT_TAGS),
+		None => TagsConfig::default().apply(keywords, &DEFAULT_TAGS),
+	}
+}
+
+// Add tags on top of the photo's tags, which already had the rules applied,
+// so only the added tags are aliased and recased
+pub(crate) fn add_tags(config: Option<&TagsConfig>, tags: &mut Vec<String>, added: &[String]) {
+	let added = match config {
+		Some(config) => config.apply(added, &[]),
+		None => TagsConfig::default().apply(added, &[]),
+	};
+
+	for tag in added {
+		if !contains(tags, &tag) {
+			tags.push(tag);
+		}
+	}
+}
+
+// The tags for a single destination, with its own rules applied over the
+// photo's tags
+pub(crate) fn destination_tags(config: Option<&TagsConfig>, metadata: &Metadata) -> Vec<String> {
+	match config {
+		Some(config) => config.apply(&metadata.tags, &[]),
+		None => metadata.tags.clone(),
+	}
+}
+
+// A tag as a hashtag, like `#NewYork` for `new york`
+pub(crate) fn hashtag(tag: &str) -> String {
+	let words: String = tag
+		.split(|c: char| !c.is_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.map(|word| {
+			let mut chars = word.chars();
+
+			match chars.next() {
+				Some(first) => first.to_uppercase().chain(chars).collect(),
+				None => String::new(),
+			}
+		})
+		.collect();
+
+	format!("#{}", words)
+}
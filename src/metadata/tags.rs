@@ -0,0 +1,213 @@
+use crate::metadata::source::MergeMode;
+use serde::{Deserialize, Deserializer};
+
+// A single value as exiftool or an overrides file might write it. exiftool's
+// JSON output uses numbers whenever a value looks like one, even for titles
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+	Text(String),
+	Integer(i64),
+	Float(f64),
+	Boolean(bool),
+}
+
+impl Scalar {
+	fn into_string(self) -> String {
+		match self {
+			Scalar::Text(text) => text.trim().into(),
+			Scalar::Integer(integer) => integer.to_string(),
+			Scalar::Float(float) => float.to_string(),
+			Scalar::Boolean(boolean) => boolean.to_string(),
+		}
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+	One(Scalar),
+	Many(Vec<Scalar>),
+}
+
+fn string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let value: Option<Scalar> = Option::deserialize(deserializer)?;
+
+	Ok(
+		value
+			.map(Scalar::into_string)
+			.filter(|value| !value.is_empty()),
+	)
+}
+
+// Lists can be written as an array, or as a single comma separated string
+fn list<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let values: Vec<String> = match Option::deserialize(deserializer)? {
+		Some(OneOrMany::One(value)) => value
+			.into_string()
+			.split(',')
+			.map(|value| value.trim().to_string())
+			.collect(),
+		Some(OneOrMany::Many(values)) => values.into_iter().map(Scalar::into_string).collect(),
+		None => Vec::new(),
+	};
+	let values: Vec<String> = values
+		.into_iter()
+		.filter(|value| !value.is_empty())
+		.collect();
+
+	Ok(non_empty(values))
+}
+
+// Raw metadata, named the way exiftool names its tags. Every source fills in
+// what it can, and the chain of sources is merged before `Metadata` is built
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct Tags {
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) make: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) model: Option<String>,
+	#[serde(default, rename = "ISO", deserialize_with = "string")]
+	pub(crate) iso: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) exposure_time: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) f_number: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) shutter_speed_value: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) aperture_value: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) focal_length: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) lens_make: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) lens_model: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) focal_length_in_35mm_format: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) exposure_compensation: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) metering_mode: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) flash: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) white_balance: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) image_width: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) image_height: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) orientation: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) title: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) description: Option<String>,
+	#[serde(default, deserialize_with = "list")]
+	pub(crate) keywords: Option<Vec<String>>,
+	#[serde(default, deserialize_with = "list")]
+	pub(crate) hierarchical_subject: Option<Vec<String>>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) rating: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) label: Option<String>,
+	#[serde(default, rename = "GPSLatitude", deserialize_with = "string")]
+	pub(crate) gps_latitude: Option<String>,
+	#[serde(default, rename = "GPSLongitude", deserialize_with = "string")]
+	pub(crate) gps_longitude: Option<String>,
+	#[serde(default, rename = "GPSAltitude", deserialize_with = "string")]
+	pub(crate) gps_altitude: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) date_time_created: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) date_time_original: Option<String>,
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) file_modify_date: Option<String>,
+	// Like `+02:00`, for the offset the camera's clock was set to
+	#[serde(default, deserialize_with = "string")]
+	pub(crate) offset_time_original: Option<String>,
+	// In UTC
+	#[serde(default, rename = "GPSDateTime", deserialize_with = "string")]
+	pub(crate) gps_date_time: Option<String>,
+}
+
+// Lists without any values are treated as missing, so they don't replace
+// values from other sources
+pub(crate) fn non_empty(values: Vec<String>) -> Option<Vec<String>> {
+	if values.is_empty() {
+		None
+	} else {
+		Some(values)
+	}
+}
+
+fn merge_field<T>(current: &mut Option<T>, new: Option<T>, mode: MergeMode) {
+	if new.is_some() && (mode == MergeMode::Override || current.is_none()) {
+		*current = new;
+	}
+}
+
+impl Tags {
+	// Combine the tags read from a later source into these
+	pub(crate) fn merge(&mut self, other: Tags, mode: MergeMode) {
+		merge_field(&mut self.make, other.make, mode);
+		merge_field(&mut self.model, other.model, mode);
+		merge_field(&mut self.iso, other.iso, mode);
+		merge_field(&mut self.exposure_time, other.exposure_time, mode);
+		merge_field(&mut self.f_number, other.f_number, mode);
+		merge_field(
+			&mut self.shutter_speed_value,
+			other.shutter_speed_value,
+			mode,
+		);
+		merge_field(&mut self.aperture_value, other.aperture_value, mode);
+		merge_field(&mut self.focal_length, other.focal_length, mode);
+		merge_field(&mut self.lens_make, other.lens_make, mode);
+		merge_field(&mut self.lens_model, other.lens_model, mode);
+		merge_field(
+			&mut self.focal_length_in_35mm_format,
+			other.focal_length_in_35mm_format,
+			mode,
+		);
+		merge_field(
+			&mut self.exposure_compensation,
+			other.exposure_compensation,
+			mode,
+		);
+		merge_field(&mut self.metering_mode, other.metering_mode, mode);
+		merge_field(&mut self.flash, other.flash, mode);
+		merge_field(&mut self.white_balance, other.white_balance, mode);
+		merge_field(&mut self.image_width, other.image_width, mode);
+		merge_field(&mut self.image_height, other.image_height, mode);
+		merge_field(&mut self.orientation, other.orientation, mode);
+		merge_field(&mut self.title, other.title, mode);
+		merge_field(&mut self.description, other.description, mode);
+		merge_field(&mut self.keywords, other.keywords, mode);
+		merge_field(
+			&mut self.hierarchical_subject,
+			other.hierarchical_subject,
+			mode,
+		);
+		merge_field(&mut self.rating, other.rating, mode);
+		merge_field(&mut self.label, other.label, mode);
+		merge_field(&mut self.gps_latitude, other.gps_latitude, mode);
+		merge_field(&mut self.gps_longitude, other.gps_longitude, mode);
+		merge_field(&mut self.gps_altitude, other.gps_altitude, mode);
+		merge_field(&mut self.date_time_created, other.date_time_created, mode);
+		merge_field(&mut self.date_time_original, other.date_time_original, mode);
+		merge_field(&mut self.file_modify_date, other.file_modify_date, mode);
+		merge_field(
+			&mut self.offset_time_original,
+			other.offset_time_original,
+			mode,
+		);
+		merge_field(&mut self.gps_date_time, other.gps_date_time, mode);
+	}
+}
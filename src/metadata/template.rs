@@ -0,0 +1,64 @@
+use crate::metadata::exif::Metadata;
+use crate::UploadError;
+
+// Fill in the `{field}` placeholders in a template with a photo's metadata,
+// like `{camera} with {lens} at {focal_length}`. `{{` and `}}` are literal
+// braces, and fields the photo doesn't have are left empty
+pub(crate) fn render(template: &str, metadata: &Metadata) -> Result<String, UploadError> {
+	render_with(template, |name| metadata.field(name))
+}
+
+// Fill in a template with fields from `field`, which gives `None` for fields
+// that don't exist
+pub(crate) fn render_with<F: Fn(&str) -> Option<String>>(
+	template: &str,
+	field: F,
+) -> Result<String, UploadError> {
+	let mut rendered = String::new();
+	let mut chars = template.chars().peekable();
+
+	while let Some(c) = chars.next() {
+		match c {
+			'{' if chars.peek() == Some(&'{') => {
+				chars.next();
+				rendered.push('{');
+			}
+			'}' if chars.peek() == Some(&'}') => {
+				chars.next();
+				rendered.push('}');
+			}
+			'{' => {
+				let mut name = String::new();
+				let mut closed = false;
+
+				for c in chars.by_ref() {
+					if c == '}' {
+						closed = true;
+						break;
+					}
+
+					name.push(c);
+				}
+
+				if !closed {
+					return Err(UploadError::MalformedConfig(Some(format!(
+						"The template \"{}\" has a {{ that isn't closed",
+						template
+					))));
+				}
+
+				let value = field(name.trim()).ok_or_else(|| {
+					UploadError::MalformedConfig(Some(format!(
+						"The template \"{}\" uses an unknown field: {}",
+						template, name
+					)))
+				})?;
+
+				rendered.push_str(&value);
+			}
+			c => rendered.push(c),
+		}
+	}
+
+	Ok(rendered.trim().into())
+}
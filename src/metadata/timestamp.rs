@@ -0,0 +1,183 @@
+use crate::metadata::tags::Tags;
+use crate::UploadError;
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+// Offsets from UTC are all whole quarter hours, so the offset worked out from
+// the GPS time is rounded to one, which also hides how stale the fix was
+const QUARTER_HOUR: i64 = 15 * 60;
+const MAX_OFFSET: i64 = 14 * 60 * 60;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct TimeConfig {
+	// The time zone photos are assumed to be taken in when they don't say, like
+	// `America/Los_Angeles`. Defaults to this computer's time zone
+	pub(crate) timezone: Option<String>,
+}
+
+// A date from the metadata, which only has an offset if the camera recorded one
+enum Timestamp {
+	Zoned(DateTime<FixedOffset>),
+	Naive(NaiveDateTime),
+}
+
+const ZONED_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f%z", "%Y:%m:%d %H:%M:%S%.f%z"];
+const NAIVE_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y:%m:%d %H:%M:%S%.f"];
+
+// Dates are in the EXIF format, like exiftool writes them, or formatted like
+// `2021-06-05T19:30:00` by the native reader, and hand written ones can use
+// either. Only dates that recorded an offset have one, which can be `Z` for UTC
+fn parse(name: &str, value: &str) -> Result<Timestamp, UploadError> {
+	let value = value.trim();
+	let utc = value
+		.strip_suffix('Z')
+		.map(|value| format!("{}+0000", value));
+	let zoned_value = utc.as_deref().unwrap_or(value);
+
+	for format in &ZONED_FORMATS {
+		if let Ok(date) = DateTime::parse_from_str(zoned_value, format) {
+			return Ok(Timestamp::Zoned(date));
+		}
+	}
+
+	for format in &NAIVE_FORMATS {
+		if let Ok(date) = NaiveDateTime::parse_from_str(value, format) {
+			return Ok(Timestamp::Naive(date));
+		}
+	}
+
+	Err(UploadError::MalformedMetadata(Some(format!(
+		"{} should be a date and time, but was {}",
+		name, value
+	))))
+}
+
+// Offsets are written as `+02:00`, and sometimes without the colon
+fn offset(value: &str) -> Option<FixedOffset> {
+	let value = value.trim().replace(':', "");
+
+	if value == "Z" {
+		return FixedOffset::east_opt(0);
+	}
+
+	let (sign, digits) = match (value.strip_prefix('+'), value.strip_prefix('-')) {
+		(Some(digits), _) => (1, digits),
+		(_, Some(digits)) => (-1, digits),
+		_ => return None,
+	};
+
+	if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+		return None;
+	}
+
+	let hours: i32 = digits[0..2].parse().ok()?;
+	let minutes: i32 = digits[2..4].parse().ok()?;
+
+	FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+// Work out the offset from how far the camera's clock was from the GPS's,
+// which is always in UTC
+fn gps_offset(local: &NaiveDateTime, gps: &str) -> Option<FixedOffset> {
+	let utc = match parse("GPSDateTime", gps).ok()? {
+		Timestamp::Zoned(date) => date.naive_utc(),
+		Timestamp::Naive(date) => date,
+	};
+	let seconds = (*local - utc).num_seconds();
+	let rounded = (seconds as f64 / QUARTER_HOUR as f64).round() as i64 * QUARTER_HOUR;
+
+	if rounded.abs() > MAX_OFFSET {
+		return None;
+	}
+
+	FixedOffset::east_opt(rounded as i32)
+}
+
+fn in_timezone<T: TimeZone>(timezone: &T, local: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+	let date = timezone.from_local_datetime(local).earliest()?;
+	let offset = date.offset().fix();
+
+	Some(date.with_timezone(&offset))
+}
+
+// When the photo was taken. Cameras usually only record the time on their
+// clock, so the offset comes from the first of:
+// - the date itself, for IPTC dates and file dates
+// - `OffsetTimeOriginal`, which newer cameras record
+// - the difference between the camera's clock and the GPS time
+// - the time zone in the config, or this computer's
+pub(crate) fn capture_time(
+	tags: &Tags,
+	path: &str,
+	config: Option<&TimeConfig>,
+) -> Result<DateTime<FixedOffset>, UploadError> {
+	let (name, value) = [
+		("DateTimeCreated", &tags.date_time_created),
+		("DateTimeOriginal", &tags.date_time_original),
+		("FileModifyDate", &tags.file_modify_date),
+	]
+	.iter()
+	.find_map(|(name, value)| value.as_ref().map(|value| (*name, value)))
+	.ok_or_else(|| UploadError::MissingMetadata(Some(format!("No date was found for {}", path))))?;
+	let local = match parse(name, value)? {
+		Timestamp::Zoned(date) => return Ok(date),
+		Timestamp::Naive(date) => date,
+	};
+
+	if let Some(offset) = tags.offset_time_original.as_deref().and_then(offset) {
+		if let Some(date) = in_timezone(&offset, &local) {
+			return Ok(date);
+		}
+	}
+
+	if let Some(offset) = tags
+		.gps_date_time
+		.as_deref()
+		.and_then(|gps| gps_offset(&local, gps))
+	{
+		if let Some(date) = in_timezone(&offset, &local) {
+			return Ok(date);
+		}
+	}
+
+	localize(&local, value, config)
+}
+
+// Place a time without an offset in the configured time zone
+fn localize(
+	local: &NaiveDateTime,
+	value: &str,
+	config: Option<&TimeConfig>,
+) -> Result<DateTime<FixedOffset>, UploadError> {
+	let date = match config.and_then(|config| config.timezone.as_ref()) {
+		Some(timezone) => {
+			let timezone: Tz = timezone.parse().map_err(|_| {
+				UploadError::MalformedConfig(Some(format!("{} isn't a known time zone", timezone)))
+			})?;
+
+			in_timezone(&timezone, local)
+		}
+		None => in_timezone(&Local, local),
+	};
+
+	date.ok_or_else(|| {
+		UploadError::MalformedMetadata(Some(format!(
+			"{} doesn't exist in the photo's time zone",
+			value
+		)))
+	})
+}
+
+// A date given by hand, which is in the configured time zone unless it has
+// an offset
+pub(crate) fn parse_time(
+	name: &str,
+	value: &str,
+	config: Option<&TimeConfig>,
+) -> Result<DateTime<FixedOffset>, UploadError> {
+	match parse(name, value)? {
+		Timestamp::Zoned(date) => Ok(date),
+		Timestamp::Naive(local) => localize(&local, value, config),
+	}
+}
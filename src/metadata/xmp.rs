@@ -0,0 +1,169 @@
+use log::debug;
+use roxmltree::Document;
+use std::ops::Range;
+
+const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+const EXIF_NAMESPACE: &str = "http://ns.adobe.com/exif/1.0/";
+const LIGHTROOM_NAMESPACE: &str = "http://ns.adobe.com/lightroom/1.0/";
+const RDF_NAMESPACE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const TIFF_NAMESPACE: &str = "http://ns.adobe.com/tiff/1.0/";
+const XMP_NAMESPACE: &str = "http://ns.adobe.com/xap/1.0/";
+const XMP_START: &str = "<x:xmpmeta";
+const XMP_END: &str = "</x:xmpmeta>";
+
+// The fields phupload cares about from an XMP packet
+#[derive(Debug, Default)]
+pub(crate) struct Xmp {
+	pub(crate) title: Option<String>,
+	pub(crate) description: Option<String>,
+	pub(crate) subject: Vec<String>,
+	// Keywords with their parents, as `Places|Europe|Paris`
+	pub(crate) hierarchical_subject: Vec<String>,
+	pub(crate) rating: Option<String>,
+	// The color label, which editors store by name
+	pub(crate) label: Option<String>,
+	// In signed decimal degrees and meters, like exiftool gives them
+	pub(crate) gps_latitude: Option<String>,
+	pub(crate) gps_longitude: Option<String>,
+	pub(crate) gps_altitude: Option<String>,
+	// The EXIF orientation, which editors write when a photo is turned
+	pub(crate) orientation: Option<String>,
+}
+
+// XMP packets are stored as plain text in JPEG, TIFF, PNG, and most RAW
+// files, so rather than understanding each container, look for the packet
+// itself
+pub(crate) fn find_packet(bytes: &[u8]) -> Option<&str> {
+	std::str::from_utf8(&bytes[packet_range(bytes)?]).ok()
+}
+
+// Where the packet is in the file
+pub(crate) fn packet_range(bytes: &[u8]) -> Option<Range<usize>> {
+	let start = find(bytes, XMP_START.as_bytes())?;
+	let end = find(&bytes[start..], XMP_END.as_bytes())? + start + XMP_END.len();
+
+	Some(start..end)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+	haystack
+		.windows(needle.len())
+		.position(|window| window == needle)
+}
+
+// The values of a property. Simple properties can be attributes of
+// `rdf:Description` or elements with text, while language alternatives and
+// bags hold each value in an `rdf:li`
+fn property(document: &Document, namespace: &str, name: &str) -> Vec<String> {
+	for node in document.descendants() {
+		if node.has_tag_name((RDF_NAMESPACE, "Description")) {
+			if let Some(value) = node.attribute((namespace, name)) {
+				return vec![value.trim().into()];
+			}
+		}
+
+		if node.is_element() && node.has_tag_name((namespace, name)) {
+			let items: Vec<String> = node
+				.descendants()
+				.filter(|child| child.has_tag_name((RDF_NAMESPACE, "li")))
+				.filter_map(|child| child.text())
+				.map(|text| text.trim().to_string())
+				.filter(|text| !text.is_empty())
+				.collect();
+
+			if !items.is_empty() {
+				return items;
+			}
+
+			return node
+				.text()
+				.map(|text| text.trim().to_string())
+				.filter(|text| !text.is_empty())
+				.into_iter()
+				.collect();
+		}
+	}
+
+	Vec::new()
+}
+
+// XMP coordinates are degrees and decimal minutes followed by the
+// hemisphere, like `45,30.912N`, or `45,30,54.7N` with seconds
+fn coordinate(value: &str) -> Option<String> {
+	let value = value.trim();
+	let hemisphere = value.chars().last()?;
+	let parts = value[..value.len() - hemisphere.len_utf8()]
+		.split(',')
+		.map(|part| part.trim().parse::<f64>().ok())
+		.collect::<Option<Vec<f64>>>()?;
+	let degrees: f64 = parts
+		.iter()
+		.zip([1.0, 60.0, 3600.0])
+		.map(|(part, divisor)| part / divisor)
+		.sum();
+
+	match hemisphere.to_ascii_uppercase() {
+		'N' | 'E' => Some(degrees.to_string()),
+		'S' | 'W' => Some((-degrees).to_string()),
+		_ => None,
+	}
+}
+
+// Altitudes are rationals, like `123/1`, with a reference of 1 below sea level
+fn altitude(value: &str, reference: Option<String>) -> Option<String> {
+	let meters = match value.split_once('/') {
+		Some((numerator, denominator)) => {
+			numerator.trim().parse::<f64>().ok()? / denominator.trim().parse::<f64>().ok()?
+		}
+		None => value.trim().parse().ok()?,
+	};
+	let sign = if reference.as_deref() == Some("1") {
+		-1.0
+	} else {
+		1.0
+	};
+
+	Some((sign * meters).to_string()).filter(|_| meters.is_finite())
+}
+
+pub(crate) fn parse(packet: &str) -> Option<Xmp> {
+	let document = Document::parse(packet)
+		.map_err(|err| debug!("Error parsing XMP: {:?}", err))
+		.ok()?;
+
+	Some(Xmp {
+		title: property(&document, DC_NAMESPACE, "title")
+			.into_iter()
+			.next(),
+		description: property(&document, DC_NAMESPACE, "description")
+			.into_iter()
+			.next(),
+		subject: property(&document, DC_NAMESPACE, "subject"),
+		hierarchical_subject: property(&document, LIGHTROOM_NAMESPACE, "hierarchicalSubject"),
+		rating: property(&document, XMP_NAMESPACE, "Rating")
+			.into_iter()
+			.next(),
+		label: property(&document, XMP_NAMESPACE, "Label")
+			.into_iter()
+			.next(),
+		gps_latitude: property(&document, EXIF_NAMESPACE, "GPSLatitude")
+			.first()
+			.and_then(|value| coordinate(value)),
+		gps_longitude: property(&document, EXIF_NAMESPACE, "GPSLongitude")
+			.first()
+			.and_then(|value| coordinate(value)),
+		gps_altitude: property(&document, EXIF_NAMESPACE, "GPSAltitude")
+			.first()
+			.and_then(|value| {
+				altitude(
+					value,
+					property(&document, EXIF_NAMESPACE, "GPSAltitudeRef")
+						.into_iter()
+						.next(),
+				)
+			}),
+		orientation: property(&document, TIFF_NAMESPACE, "Orientation")
+			.into_iter()
+			.next(),
+	})
+}
@@ -0,0 +1,4 @@
+pub(crate) mod paths;
+pub(crate) mod publish;
+pub(crate) mod report;
+pub(crate) mod update;
@@ -0,0 +1,106 @@
+use crate::UploadError;
+use glob::glob;
+use log::debug;
+use std::collections::HashSet;
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+// File extensions picked up when walking a directory or expanding a glob.
+// Files passed explicitly are always used, whatever their extension
+const PHOTO_EXTENSIONS: [&str; 16] = [
+	"jpg", "jpeg", "tif", "tiff", "png", "webp", "heic", "heif", "dng", "arw", "cr2", "cr3", "nef",
+	"raf", "orf", "rw2",
+];
+
+fn is_photo(path: &Path) -> bool {
+	let is_hidden = path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.map(|name| name.starts_with('.'))
+		.unwrap_or(true);
+
+	!is_hidden
+		&& path
+			.extension()
+			.and_then(|extension| extension.to_str())
+			.map(|extension| PHOTO_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+			.unwrap_or(false)
+}
+
+fn is_glob(input: &str) -> bool {
+	input.contains(['*', '?', '['])
+}
+
+fn walk_directory(
+	directory: &Path,
+	recursive: bool,
+	photos: &mut Vec<PathBuf>,
+) -> Result<(), UploadError> {
+	let entries = read_dir(directory).map_err(|err| {
+		debug!("Error reading directory {:?}: {:?}", directory, err);
+
+		UploadError::UnknownError(Some(format!(
+			"Unable to read the directory {}",
+			directory.display()
+		)))
+	})?;
+	let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+
+	paths.sort();
+
+	for path in paths {
+		if path.is_dir() {
+			if recursive {
+				walk_directory(&path, recursive, photos)?;
+			}
+		} else if is_photo(&path) {
+			photos.push(path);
+		}
+	}
+
+	Ok(())
+}
+
+// Expand the paths given on the command line into a list of photos. Inputs
+// can be files, directories (optionally walked recursively), or globs
+pub(crate) fn collect_photos<'a, I>(inputs: I, recursive: bool) -> Result<Vec<PathBuf>, UploadError>
+where
+	I: IntoIterator<Item = &'a str>,
+{
+	let mut photos: Vec<PathBuf> = Vec::new();
+
+	for input in inputs {
+		let path = Path::new(input);
+
+		if path.is_dir() {
+			walk_directory(path, recursive, &mut photos)?;
+		} else if path.is_file() {
+			photos.push(path.to_path_buf());
+		} else if is_glob(input) {
+			let matches = glob(input).map_err(|err| {
+				debug!("Invalid glob pattern {}: {:?}", input, err);
+
+				UploadError::UnknownError(Some(format!("Invalid glob pattern: {}", input)))
+			})?;
+
+			for matched in matches.flatten() {
+				if matched.is_dir() {
+					walk_directory(&matched, recursive, &mut photos)?;
+				} else if is_photo(&matched) {
+					photos.push(matched);
+				}
+			}
+		} else {
+			return Err(UploadError::UnknownError(Some(format!(
+				"No photo was found at {}",
+				input
+			))));
+		}
+	}
+
+	let mut seen: HashSet<PathBuf> = HashSet::new();
+
+	photos.retain(|photo| seen.insert(photo.canonicalize().unwrap_or_else(|_| photo.clone())));
+
+	Ok(photos)
+}
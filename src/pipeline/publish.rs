@@ -0,0 +1,264 @@
+use crate::metadata::config::Config;
+use crate::metadata::edits::MetadataEdits;
+use crate::metadata::exif::MetadataReader;
+use crate::metadata::ledger::{content_hash, write_ledger, Ledger};
+use crate::pipeline::report::{DestinationResult, Outcome};
+use crate::publishers::cloudinary::Cloudinary;
+use crate::publishers::flickr::Flickr;
+use crate::publishers::script::Script;
+use crate::{PhotoDestination, Upload, UploadError};
+use log::{debug, error, info, warn};
+use serde_json::{json, to_string_pretty};
+use std::path::Path;
+
+pub(crate) struct PublishOptions {
+	// Attempt every destination, even after one of them fails
+	pub(crate) keep_going: bool,
+	// Publish to destinations the ledger says already have the photo
+	pub(crate) force: bool,
+	pub(crate) edits: MetadataEdits,
+	// Why flickr couldn't be authenticated, which fails it for every photo
+	pub(crate) flickr_error: Option<UploadError>,
+}
+
+// Read a photo's metadata, with any edits from the command line applied
+pub(crate) fn read_photo(
+	config: &Config,
+	reader: &MetadataReader,
+	path: &Path,
+	edits: &MetadataEdits,
+) -> Result<Upload, UploadError> {
+	let photo_path = path.to_string_lossy().to_string();
+	let mut metadata = reader.read(config, &photo_path)?;
+	let hash = content_hash(&photo_path)?;
+
+	edits.apply(&mut metadata, config)?;
+
+	debug!("metadata: {:?}", metadata);
+
+	Ok(Upload {
+		path: photo_path,
+		metadata,
+		url: None,
+		hash,
+	})
+}
+
+// Upload to a single destination, unless the ledger shows it's already there.
+// The outcome is saved to the ledger right away, so a batch that dies halfway
+// through doesn't publish duplicates when it's run again
+fn publish_to<D: PhotoDestination>(
+	config: &D::Config,
+	photo: &Upload,
+	ledger: &mut Ledger,
+	force: bool,
+) -> Result<Outcome, UploadError> {
+	let destination = D::destination(config);
+
+	if !force {
+		if let Some(publication) = ledger.publication(&photo.hash, &destination) {
+			info!("{} is already on {}, skipping", photo.path, destination);
+
+			return Ok(Outcome::AlreadyPublished(publication.id.clone()));
+		}
+	}
+
+	let result = D::upload(config, photo);
+
+	match &result {
+		Ok(id) => ledger.record(
+			&photo.hash,
+			&photo.path,
+			&photo.metadata.title,
+			&destination,
+			id,
+		),
+		Err(err) => ledger.record_failure(
+			&photo.hash,
+			&photo.path,
+			&photo.metadata.title,
+			&destination,
+			err,
+		),
+	};
+
+	if let Err(err) = write_ledger(ledger) {
+		warn!("Couldn't save {} to the ledger: {}", photo.path, err);
+	}
+
+	result.map(Outcome::Published)
+}
+
+// Print what would be sent to a single destination, unless the ledger shows
+// it's already there
+fn preview_to<D: PhotoDestination>(
+	config: &D::Config,
+	photo: &Upload,
+	ledger: &Ledger,
+	force: bool,
+) -> Result<Outcome, UploadError> {
+	let destination = D::destination(config);
+
+	if !force {
+		if let Some(publication) = ledger.publication(&photo.hash, &destination) {
+			return Ok(Outcome::AlreadyPublished(publication.id.clone()));
+		}
+	}
+
+	let request = D::preview(config, photo)?;
+
+	info!(
+		"{}",
+		to_string_pretty(&json!({ destination: request })).unwrap_or_default()
+	);
+
+	Ok(Outcome::Published(String::new()))
+}
+
+// Keep track of how a destination went, and return whether the rest of the
+// destinations should still be attempted
+pub(crate) fn record(
+	results: &mut Vec<DestinationResult>,
+	destination: String,
+	result: Result<Outcome, UploadError>,
+	keep_going: bool,
+) -> bool {
+	let succeeded = result.is_ok();
+
+	if let Err(err) = &result {
+		error!("{} failed: {}", destination, err);
+	}
+
+	results.push(DestinationResult {
+		destination,
+		result,
+	});
+
+	succeeded || keep_going
+}
+
+// Run a single photo through every configured destination: cloudinary first,
+// since its URL is handed to the scripts, then the scripts, then flickr. Unless
+// `keep_going` is set, the first failure stops the remaining destinations
+pub(crate) fn publish_photo(
+	config: &Config,
+	reader: &MetadataReader,
+	ledger: &mut Ledger,
+	path: &Path,
+	options: &PublishOptions,
+) -> Result<Vec<DestinationResult>, UploadError> {
+	info!("Publishing {}...", path.display());
+
+	let mut photo_to_upload = read_photo(config, reader, path, &options.edits)?;
+	let mut results: Vec<DestinationResult> = Vec::new();
+
+	if let Some(cloudinary_config) = &config.cloudinary {
+		let result =
+			publish_to::<Cloudinary>(cloudinary_config, &photo_to_upload, ledger, options.force);
+
+		photo_to_upload.url = result.as_ref().ok().map(|outcome| outcome.id().into());
+
+		if !record(
+			&mut results,
+			Cloudinary::destination(cloudinary_config),
+			result,
+			options.keep_going,
+		) {
+			return Ok(results);
+		}
+	}
+
+	if let Some(scripts) = &config.script {
+		for script in scripts {
+			let result = publish_to::<Script>(script, &photo_to_upload, ledger, options.force);
+
+			if !record(
+				&mut results,
+				Script::destination(script),
+				result,
+				options.keep_going,
+			) {
+				return Ok(results);
+			}
+		}
+	}
+
+	if let Some(flickr_config) = &config.flickr {
+		let result = match &options.flickr_error {
+			Some(err) => Err(err.clone()),
+			None => publish_to::<Flickr>(flickr_config, &photo_to_upload, ledger, options.force),
+		};
+
+		record(
+			&mut results,
+			Flickr::destination(flickr_config),
+			result,
+			options.keep_going,
+		);
+	}
+
+	Ok(results)
+}
+
+// Walk through the same steps as `publish_photo`, but only print what each
+// destination would receive. Nothing is uploaded and no scripts are run
+pub(crate) fn preview_photo(
+	config: &Config,
+	reader: &MetadataReader,
+	ledger: &Ledger,
+	path: &Path,
+	options: &PublishOptions,
+) -> Result<Vec<DestinationResult>, UploadError> {
+	info!("Previewing {}...", path.display());
+
+	let mut photo_to_upload = read_photo(config, reader, path, &options.edits)?;
+	let mut results: Vec<DestinationResult> = Vec::new();
+
+	if let Some(cloudinary_config) = &config.cloudinary {
+		let extension = path
+			.extension()
+			.map(|extension| extension.to_string_lossy().to_lowercase())
+			.unwrap_or_default();
+		let result =
+			preview_to::<Cloudinary>(cloudinary_config, &photo_to_upload, ledger, options.force)
+				.and_then(|outcome| match outcome {
+					// Cloudinary responds with `<public_id>.<format>`, which is what the
+					// scripts would be given as the url
+					Outcome::Published(_) => Ok(Outcome::Published(format!(
+						"{}.{}",
+						Cloudinary::public_id(cloudinary_config, &photo_to_upload)?,
+						extension
+					))),
+					outcome => Ok(outcome),
+				});
+
+		photo_to_upload.url = result.as_ref().ok().map(|outcome| outcome.id().into());
+		record(
+			&mut results,
+			Cloudinary::destination(cloudinary_config),
+			result,
+			true,
+		);
+	}
+
+	if let Some(scripts) = &config.script {
+		for script in scripts {
+			let result = preview_to::<Script>(script, &photo_to_upload, ledger, options.force);
+
+			record(&mut results, Script::destination(script), result, true);
+		}
+	}
+
+	if let Some(flickr_config) = &config.flickr {
+		let result = preview_to::<Flickr>(flickr_config, &photo_to_upload, ledger, options.force);
+
+		record(
+			&mut results,
+			Flickr::destination(flickr_config),
+			result,
+			true,
+		);
+	}
+
+	Ok(results)
+}
@@ -0,0 +1,162 @@
+use crate::UploadError;
+use log::{error, info};
+use std::path::PathBuf;
+
+pub(crate) enum Outcome {
+	Published(String),
+	// The ledger shows the destination already has the photo
+	AlreadyPublished(String),
+	// The ledger shows the photo was never published to the destination
+	NotPublished,
+}
+
+impl Outcome {
+	// The id the destination gave the photo
+	pub(crate) fn id(&self) -> &str {
+		match self {
+			Outcome::Published(id) | Outcome::AlreadyPublished(id) => id,
+			Outcome::NotPublished => "",
+		}
+	}
+}
+
+pub(crate) struct DestinationResult {
+	pub(crate) destination: String,
+	pub(crate) result: Result<Outcome, UploadError>,
+}
+
+pub(crate) struct PhotoResult {
+	pub(crate) path: PathBuf,
+	pub(crate) result: Result<Vec<DestinationResult>, UploadError>,
+}
+
+struct Row {
+	photo: String,
+	destination: String,
+	status: &'static str,
+	detail: String,
+}
+
+// Print a table with one row per photo and destination once the whole batch
+// has run, and return how many of those rows failed
+pub(crate) fn print_report(results: &[PhotoResult], outcome: &'static str) -> usize {
+	let mut rows: Vec<Row> = Vec::new();
+
+	for PhotoResult { path, result } in results {
+		let photo = path.display().to_string();
+
+		match result {
+			Ok(destinations) if destinations.is_empty() => rows.push(Row {
+				photo,
+				destination: "-".into(),
+				status: "skipped",
+				detail: "No destinations are configured".into(),
+			}),
+			Ok(destinations) => {
+				for DestinationResult {
+					destination,
+					result,
+				} in destinations
+				{
+					rows.push(match result {
+						Ok(Outcome::Published(id)) => Row {
+							photo: photo.clone(),
+							destination: destination.clone(),
+							status: outcome,
+							detail: id.clone(),
+						},
+						Ok(Outcome::AlreadyPublished(id)) => Row {
+							photo: photo.clone(),
+							destination: destination.clone(),
+							status: "skipped",
+							detail: format!("Already published as {}", id),
+						},
+						Ok(Outcome::NotPublished) => Row {
+							photo: photo.clone(),
+							destination: destination.clone(),
+							status: "skipped",
+							detail: "Not published here yet".into(),
+						},
+						Err(err) => Row {
+							photo: photo.clone(),
+							destination: destination.clone(),
+							status: "failed",
+							detail: err.to_string(),
+						},
+					});
+				}
+			}
+			Err(err) => rows.push(Row {
+				photo,
+				destination: "-".into(),
+				status: "failed",
+				detail: err.to_string(),
+			}),
+		}
+	}
+
+	info!("Summary for {} photo(s):", results.len());
+
+	let failures = rows.iter().filter(|row| row.status == "failed").count();
+	let table: Vec<(Vec<String>, bool)> = rows
+		.into_iter()
+		.map(|row| {
+			let failed = row.status == "failed";
+
+			(
+				vec![row.photo, row.destination, row.status.into(), row.detail],
+				failed,
+			)
+		})
+		.collect();
+
+	print_table(&["PHOTO", "DESTINATION", "RESULT", "DETAIL"], &table);
+
+	failures
+}
+
+// Print rows as aligned columns under a header. Rows flagged as failed are
+// logged as errors so they stand out
+pub(crate) fn print_table(headers: &[&str], rows: &[(Vec<String>, bool)]) {
+	let widths: Vec<usize> = headers
+		.iter()
+		.enumerate()
+		.map(|(index, header)| {
+			rows
+				.iter()
+				.filter_map(|(columns, _)| columns.get(index))
+				.map(|column| column.chars().count())
+				.max()
+				.unwrap_or(0)
+				.max(header.len())
+		})
+		.collect();
+	let format_row = |columns: Vec<&str>| -> String {
+		let last = columns.len().saturating_sub(1);
+
+		columns
+			.iter()
+			.enumerate()
+			.map(|(index, column)| {
+				if index == last {
+					column.to_string()
+				} else {
+					format!("{:width$}", column, width = widths[index])
+				}
+			})
+			.collect::<Vec<String>>()
+			.join("  ")
+	};
+
+	info!("{}", format_row(headers.to_vec()));
+
+	for (columns, failed) in rows {
+		let line = format_row(columns.iter().map(|column| column.as_str()).collect());
+
+		if *failed {
+			error!("{}", line);
+		} else {
+			info!("{}", line);
+		}
+	}
+}
@@ -0,0 +1,131 @@
+use crate::metadata::config::Config;
+use crate::metadata::edits::MetadataEdits;
+use crate::metadata::exif::MetadataReader;
+use crate::metadata::ledger::{write_ledger, Ledger};
+use crate::pipeline::publish::{read_photo, record};
+use crate::pipeline::report::{DestinationResult, Outcome};
+use crate::publishers::cloudinary::{Cloudinary, DESTINATION as CLOUDINARY};
+use crate::publishers::flickr::Flickr;
+use crate::publishers::script::Script;
+use crate::{PhotoDestination, Upload, UploadError};
+use log::{info, warn};
+use std::path::Path;
+
+// Push the photo's current metadata to a single destination, if the ledger
+// shows it was published there
+fn update_to<D: PhotoDestination>(
+	config: &D::Config,
+	photo: &Upload,
+	ledger: &mut Ledger,
+) -> Result<Outcome, UploadError> {
+	let destination = D::destination(config);
+	let id = match ledger.publication(&photo.hash, &destination) {
+		Some(publication) => publication.id.clone(),
+		None => return Ok(Outcome::NotPublished),
+	};
+	let result = D::update(config, &id, photo);
+
+	match &result {
+		Ok(id) => ledger.record_update(
+			&photo.hash,
+			&photo.path,
+			&photo.metadata.title,
+			&destination,
+			id,
+		),
+		Err(err) => ledger.record_failure(
+			&photo.hash,
+			&photo.path,
+			&photo.metadata.title,
+			&destination,
+			err,
+		),
+	};
+
+	if let Err(err) = write_ledger(ledger) {
+		warn!("Couldn't save {} to the ledger: {}", photo.path, err);
+	}
+
+	result.map(Outcome::Published)
+}
+
+// Re-read a photo's metadata and push it to every destination it was already
+// published to. Editing the metadata changes the file's hash, so the photo is
+// looked up by its path as well
+pub(crate) fn update_photo(
+	config: &Config,
+	reader: &MetadataReader,
+	ledger: &mut Ledger,
+	path: &Path,
+	keep_going: bool,
+	edits: &MetadataEdits,
+	flickr_error: Option<&UploadError>,
+) -> Result<Vec<DestinationResult>, UploadError> {
+	info!("Updating {}...", path.display());
+
+	let mut photo_to_update = read_photo(config, reader, path, edits)?;
+	let published_hash = match ledger.find(&photo_to_update.hash, &photo_to_update.path) {
+		Some((published_hash, _)) => published_hash.clone(),
+		None => {
+			return Err(UploadError::UnknownError(Some(
+				"The photo hasn't been published yet, so there's nothing to update".into(),
+			)))
+		}
+	};
+
+	if published_hash != photo_to_update.hash {
+		ledger.rehash(&published_hash, &photo_to_update.hash);
+	}
+
+	let mut results: Vec<DestinationResult> = Vec::new();
+
+	if let Some(cloudinary_config) = &config.cloudinary {
+		let result = update_to::<Cloudinary>(cloudinary_config, &photo_to_update, ledger);
+
+		if !record(
+			&mut results,
+			Cloudinary::destination(cloudinary_config),
+			result,
+			keep_going,
+		) {
+			return Ok(results);
+		}
+	}
+
+	// The scripts get the photo's URL from the last time cloudinary had it,
+	// even if it couldn't be updated this time, rather than an empty one
+	photo_to_update.url = ledger
+		.publication(&photo_to_update.hash, CLOUDINARY)
+		.map(|publication| publication.id.clone());
+
+	if let Some(scripts) = &config.script {
+		for script in scripts {
+			let result = update_to::<Script>(script, &photo_to_update, ledger);
+
+			if !record(
+				&mut results,
+				Script::destination(script),
+				result,
+				keep_going,
+			) {
+				return Ok(results);
+			}
+		}
+	}
+
+	if let Some(flickr_config) = &config.flickr {
+		let result = match flickr_error {
+			Some(err) => Err(err.clone()),
+			None => update_to::<Flickr>(flickr_config, &photo_to_update, ledger),
+		};
+
+		record(
+			&mut results,
+			Flickr::destination(flickr_config),
+			result,
+			keep_going,
+		);
+	}
+
+	Ok(results)
+}
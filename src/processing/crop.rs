@@ -0,0 +1,215 @@
+use crate::processing::watermark::color;
+use crate::UploadError;
+use image::imageops::{overlay, FilterType};
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_BACKGROUND: &str = "#ffffff";
+// Ratios closer than this are treated as the same
+const TOLERANCE: f64 = 0.005;
+// Smart crops look for detail in a copy this wide, which is plenty to find
+// the subject and much faster than the full photo
+const SALIENCY_WIDTH: u32 = 256;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CropMode {
+	// Keep the middle of the photo
+	#[default]
+	Center,
+	// Keep the part of the photo with the most detail
+	Smart,
+	// Keep the whole photo, with bars of the background color around it
+	Pad,
+}
+
+// The shape a destination needs photos to be. Either `ratio` for an exact
+// shape, or `min_ratio` and `max_ratio` for a range, with photos outside it
+// brought to the nearest end. Ratios are width to height, like `4:5`
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct CropConfig {
+	pub(crate) ratio: Option<String>,
+	pub(crate) min_ratio: Option<String>,
+	pub(crate) max_ratio: Option<String>,
+	#[serde(default)]
+	pub(crate) mode: CropMode,
+	// Like `#ffffff`, for padding
+	pub(crate) background: Option<String>,
+}
+
+// `4:5`, `1.91:1`, or `1.5`, as width over height
+fn ratio(name: &str, value: &str) -> Result<f64, UploadError> {
+	let parsed = match value.split_once(':') {
+		Some((width, height)) => width
+			.trim()
+			.parse::<f64>()
+			.and_then(|width| Ok(width / height.trim().parse::<f64>()?)),
+		None => value.trim().parse::<f64>(),
+	};
+
+	parsed
+		.ok()
+		.filter(|ratio| ratio.is_finite() && *ratio > 0.0)
+		.ok_or_else(|| {
+			UploadError::MalformedConfig(Some(format!(
+				"The crop's {} should be like 4:5, but was {}",
+				name, value
+			)))
+		})
+}
+
+impl CropConfig {
+	// The ratio a photo with `current` ratio should be brought to, if any
+	fn target(&self, current: f64) -> Result<Option<f64>, UploadError> {
+		let parse = |name: &str, value: &Option<String>| {
+			value.as_ref().map(|value| ratio(name, value)).transpose()
+		};
+		let target = match parse("ratio", &self.ratio)? {
+			Some(ratio) => ratio,
+			None => {
+				let min = parse("min_ratio", &self.min_ratio)?.unwrap_or(0.0);
+				let max = parse("max_ratio", &self.max_ratio)?.unwrap_or(f64::INFINITY);
+
+				if min > max {
+					return Err(UploadError::MalformedConfig(Some(
+						"The crop's min_ratio is wider than its max_ratio".into(),
+					)));
+				}
+
+				current.clamp(min, max)
+			}
+		};
+
+		Ok(Some(target).filter(|target| (target - current).abs() > TOLERANCE))
+	}
+
+	pub(crate) fn apply(&self, image: DynamicImage) -> Result<DynamicImage, UploadError> {
+		let (width, height) = image.dimensions();
+		let current = f64::from(width) / f64::from(height);
+		let target = match self.target(current)? {
+			Some(target) => target,
+			None => return Ok(image),
+		};
+
+		if self.mode == CropMode::Pad {
+			return self.pad(image, target);
+		}
+
+		// Crop whichever side is too long
+		let (crop_width, crop_height) = if current > target {
+			(((f64::from(height) * target).round() as u32).max(1), height)
+		} else {
+			(width, ((f64::from(width) / target).round() as u32).max(1))
+		};
+		let (x, y) = match self.mode {
+			CropMode::Smart => salient_origin(&image, crop_width, crop_height),
+			_ => ((width - crop_width) / 2, (height - crop_height) / 2),
+		};
+
+		Ok(image.crop_imm(x, y, crop_width, crop_height))
+	}
+
+	// Grow whichever side is too short, and center the photo on the background
+	fn pad(&self, image: DynamicImage, target: f64) -> Result<DynamicImage, UploadError> {
+		let (width, height) = image.dimensions();
+		let background =
+			color(self.background.as_deref().unwrap_or(DEFAULT_BACKGROUND)).ok_or_else(|| {
+				UploadError::MalformedConfig(Some("The crop's background should be like #ffffff".into()))
+			})?;
+		let (padded_width, padded_height) = if f64::from(width) / f64::from(height) > target {
+			(width, (f64::from(width) / target).round() as u32)
+		} else {
+			((f64::from(height) * target).round() as u32, height)
+		};
+		let has_alpha = image.color().has_alpha();
+		let mut canvas = RgbaImage::from_pixel(padded_width, padded_height, background);
+
+		overlay(
+			&mut canvas,
+			&image.into_rgba8(),
+			i64::from((padded_width - width) / 2),
+			i64::from((padded_height - height) / 2),
+		);
+
+		Ok(if has_alpha {
+			DynamicImage::from(canvas)
+		} else {
+			DynamicImage::from(DynamicImage::from(canvas).into_rgb8())
+		})
+	}
+}
+
+// How much detail each pixel of a small grayscale copy has, as the difference
+// from its neighbours. Sharp, busy areas are usually the subject, while sky,
+// walls, and out of focus backgrounds are smooth
+fn detail(image: &DynamicImage) -> (Vec<f64>, u32, u32) {
+	let small = image
+		.resize(SALIENCY_WIDTH, SALIENCY_WIDTH, FilterType::Triangle)
+		.into_luma8();
+	let (width, height) = small.dimensions();
+	let mut energy = vec![0.0; (width * height) as usize];
+
+	for y in 0..height {
+		for x in 0..width {
+			let value = f64::from(small.get_pixel(x, y)[0]);
+			let right = f64::from(small.get_pixel((x + 1).min(width - 1), y)[0]);
+			let below = f64::from(small.get_pixel(x, (y + 1).min(height - 1))[0]);
+
+			energy[(y * width + x) as usize] = (value - right).abs() + (value - below).abs();
+		}
+	}
+
+	(energy, width, height)
+}
+
+// Slide the crop along the side being cropped, and keep the spot with the
+// most detail
+fn salient_origin(image: &DynamicImage, crop_width: u32, crop_height: u32) -> (u32, u32) {
+	let (width, height) = image.dimensions();
+	let (energy, small_width, small_height) = detail(image);
+	let horizontal = crop_width < width;
+	// The detail in each column or row of the small copy
+	let totals: Vec<f64> = if horizontal {
+		(0..small_width)
+			.map(|x| {
+				(0..small_height)
+					.map(|y| energy[(y * small_width + x) as usize])
+					.sum()
+			})
+			.collect()
+	} else {
+		(0..small_height)
+			.map(|y| {
+				(0..small_width)
+					.map(|x| energy[(y * small_width + x) as usize])
+					.sum()
+			})
+			.collect()
+	};
+	let (length, crop_length) = if horizontal {
+		(width, crop_width)
+	} else {
+		(height, crop_height)
+	};
+	let window = ((f64::from(crop_length) / f64::from(length)) * totals.len() as f64)
+		.round()
+		.max(1.0) as usize;
+	let best = (0..=totals.len().saturating_sub(window))
+		.map(|start| (start, totals[start..start + window].iter().sum::<f64>()))
+		.fold((0, f64::MIN), |best, candidate| {
+			if candidate.1 > best.1 {
+				candidate
+			} else {
+				best
+			}
+		})
+		.0;
+	let offset = ((best as f64 / totals.len() as f64) * f64::from(length)).round() as u32;
+	let offset = offset.min(length - crop_length);
+
+	if horizontal {
+		(offset, 0)
+	} else {
+		(0, offset)
+	}
+}
@@ -0,0 +1,367 @@
+use crate::metadata::dimensions::Dimensions;
+#[cfg(feature = "native-metadata")]
+use crate::metadata::native::tiff_exif;
+use crate::metadata::privacy::{
+	sanitize, sanitize_exif, upright_exif, PrivacyConfig, SanitizedPhoto,
+};
+use crate::processing::crop::CropConfig;
+use crate::processing::watermark::WatermarkConfig;
+use crate::UploadError;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageEncoder, ImageFormat, ImageReader, ImageResult};
+use log::debug;
+use moxcms::{ColorProfile, Layout, TransformOptions};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const DEFAULT_QUALITY: u8 = 90;
+
+// AVIF encoding is slow at every speed, so use the fastest that still
+// compresses well
+const AVIF_SPEED: u8 = 6;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OutputFormat {
+	Jpeg,
+	Png,
+	// WebP is always lossless, so `quality` doesn't apply
+	Webp,
+	Avif,
+}
+
+impl OutputFormat {
+	fn extension(self) -> &'static str {
+		match self {
+			OutputFormat::Jpeg => "jpg",
+			OutputFormat::Png => "png",
+			OutputFormat::Webp => "webp",
+			OutputFormat::Avif => "avif",
+		}
+	}
+
+	fn has_alpha(self) -> bool {
+		self != OutputFormat::Jpeg
+	}
+}
+
+// How the photo a destination is sent is made from the original. Without
+// any of these, the original is sent as it is
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct ProcessingConfig {
+	// The longest the width or height can be, in pixels
+	pub(crate) max_long_edge: Option<u32>,
+	// Defaults to the original's format for JPEG, PNG, and WebP photos, and
+	// JPEG for everything else
+	pub(crate) format: Option<OutputFormat>,
+	// From 1 to 100, for JPEG and AVIF
+	pub(crate) quality: Option<u8>,
+	// Convert from the photo's color profile to sRGB, for sites that ignore
+	// color profiles
+	#[serde(default)]
+	pub(crate) srgb: bool,
+	pub(crate) crop: Option<CropConfig>,
+	pub(crate) watermark: Option<WatermarkConfig>,
+}
+
+impl ProcessingConfig {
+	fn changes_file(&self) -> bool {
+		self.max_long_edge.is_some()
+			|| self.format.is_some()
+			|| self.quality.is_some()
+			|| self.srgb
+			|| self.crop.is_some()
+			|| self.watermark.is_some()
+	}
+}
+
+// The photo to upload, which is a processed copy when the destination's
+// processing settings call for one, and otherwise the original with any
+// private details removed. Copies are deleted when this is dropped
+pub(crate) enum Derivative {
+	Original(SanitizedPhoto),
+	Processed {
+		path: PathBuf,
+		// The copy's own size, which cropping and resizing change
+		dimensions: Dimensions,
+		_directory: TempDir,
+	},
+}
+
+impl Derivative {
+	pub(crate) fn path(&self) -> &Path {
+		match self {
+			Derivative::Original(photo) => photo.path(),
+			Derivative::Processed { path, .. } => path,
+		}
+	}
+
+	// The size of a processed copy. Originals are the size their metadata says
+	pub(crate) fn dimensions(&self) -> Option<&Dimensions> {
+		match self {
+			Derivative::Original(_) => None,
+			Derivative::Processed { dimensions, .. } => Some(dimensions),
+		}
+	}
+}
+
+fn unreadable(path: &Path) -> UploadError {
+	UploadError::MalformedMetadata(Some(format!(
+		"Unable to read the image data in {}",
+		path.display()
+	)))
+}
+
+fn unwritable() -> UploadError {
+	UploadError::UnknownError(Some(
+		"Unable to create a processed copy of the photo".into(),
+	))
+}
+
+// Convert the pixels from the photo's color profile to sRGB. Photos without a
+// profile, or with one that can't be used, are assumed to already be sRGB
+fn to_srgb(image: DynamicImage, icc_profile: &[u8]) -> DynamicImage {
+	let source = match ColorProfile::new_from_slice(icc_profile) {
+		Ok(profile) => profile,
+		Err(err) => {
+			debug!("Error reading the color profile: {:?}", err);
+
+			return image;
+		}
+	};
+	let srgb = ColorProfile::new_srgb();
+	let (width, height) = (image.width(), image.height());
+	let (layout, original) = if image.color().has_alpha() {
+		(Layout::Rgba, image.into_rgba8().into_raw())
+	} else {
+		(Layout::Rgb, image.into_rgb8().into_raw())
+	};
+	let mut pixels = vec![0; original.len()];
+	let converted = source
+		.create_transform_8bit(layout, &srgb, layout, TransformOptions::default())
+		.and_then(|transform| transform.transform(&original, &mut pixels));
+
+	if let Err(err) = converted {
+		debug!("Error converting to sRGB: {:?}", err);
+
+		pixels = original;
+	}
+
+	match layout {
+		Layout::Rgba => image::RgbaImage::from_raw(width, height, pixels).map(DynamicImage::from),
+		_ => image::RgbImage::from_raw(width, height, pixels).map(DynamicImage::from),
+	}
+	.expect("The converted pixels are the same size as the originals")
+}
+
+// Without the native reader, TIFFs are copied without their EXIF data
+#[cfg(not(feature = "native-metadata"))]
+fn tiff_exif(_path: &Path) -> Option<Vec<u8>> {
+	None
+}
+
+// The metadata that's carried over from the original
+struct Carried {
+	icc_profile: Option<Vec<u8>>,
+	exif: Option<Vec<u8>>,
+}
+
+fn encode(
+	image: &DynamicImage,
+	format: OutputFormat,
+	quality: u8,
+	carried: Carried,
+	path: &Path,
+) -> Result<(), UploadError> {
+	let image = if format.has_alpha() && image.color().has_alpha() {
+		DynamicImage::from(image.to_rgba8())
+	} else {
+		DynamicImage::from(image.to_rgb8())
+	};
+	let file = File::create(path).map_err(|err| {
+		debug!("Error creating {:?}: {:?}", path, err);
+
+		unwritable()
+	})?;
+	let writer = BufWriter::new(file);
+
+	match format {
+		OutputFormat::Jpeg => write(
+			JpegEncoder::new_with_quality(writer, quality),
+			&image,
+			carried,
+		),
+		OutputFormat::Png => write(PngEncoder::new(writer), &image, carried),
+		OutputFormat::Webp => write(WebPEncoder::new_lossless(writer), &image, carried),
+		OutputFormat::Avif => write(
+			AvifEncoder::new_with_speed_quality(writer, AVIF_SPEED, quality),
+			&image,
+			carried,
+		),
+	}
+	.map_err(|err| {
+		debug!("Error encoding {:?}: {:?}", path, err);
+
+		unwritable()
+	})
+}
+
+fn write<E: ImageEncoder>(
+	mut encoder: E,
+	image: &DynamicImage,
+	Carried { icc_profile, exif }: Carried,
+) -> ImageResult<()> {
+	// Not every format can hold these, and the photo is still worth sending
+	// without them
+	if let Some(icc_profile) = icc_profile {
+		if let Err(err) = encoder.set_icc_profile(icc_profile) {
+			debug!("Unable to keep the color profile: {:?}", err);
+		}
+	}
+
+	if let Some(exif) = exif {
+		if let Err(err) = encoder.set_exif_metadata(exif) {
+			debug!("Unable to keep the EXIF data: {:?}", err);
+		}
+	}
+
+	encoder.write_image(
+		image.as_bytes(),
+		image.width(),
+		image.height(),
+		image.color().into(),
+	)
+}
+
+// Make the copy of the photo a destination is sent. The EXIF data is kept,
+// minus anything the privacy settings remove, so the destination still knows
+// how the photo was taken and which way up it goes. XMP and IPTC data aren't
+// kept, which also means any photo the image reader understands can be
+// processed, whatever its privacy settings
+pub(crate) fn derive(
+	config: Option<&ProcessingConfig>,
+	privacy: Option<&PrivacyConfig>,
+	path: &str,
+) -> Result<Derivative, UploadError> {
+	let config = match config {
+		Some(config) if config.changes_file() => config,
+		_ => return sanitize(privacy, path).map(Derivative::Original),
+	};
+
+	if config.format == Some(OutputFormat::Webp) && config.quality.is_some() {
+		return Err(UploadError::MalformedConfig(Some(
+			"WebP copies are always lossless, so quality can't be set with format = \"webp\"".into(),
+		)));
+	}
+
+	let path = Path::new(path);
+	let reader = ImageReader::open(path)
+		.and_then(|reader| reader.with_guessed_format())
+		.map_err(|err| {
+			debug!("Error opening {:?}: {:?}", path, err);
+
+			unreadable(path)
+		})?;
+	let is_tiff = reader.format() == Some(ImageFormat::Tiff);
+	let format = config.format.unwrap_or(match reader.format() {
+		Some(ImageFormat::Png) => OutputFormat::Png,
+		Some(ImageFormat::WebP) => OutputFormat::Webp,
+		_ => OutputFormat::Jpeg,
+	});
+	let mut decoder = reader.into_decoder().map_err(|err| {
+		debug!("Error decoding {:?}: {:?}", path, err);
+
+		unreadable(path)
+	})?;
+	let icc_profile = decoder.icc_profile().ok().flatten();
+	let mut exif = decoder.exif_metadata().ok().flatten();
+
+	if exif.is_none() && is_tiff {
+		exif = tiff_exif(path);
+	}
+
+	// Copies are turned upright, so watermarks end up where they're meant to
+	// be, and sites that ignore the orientation show the photo the right way up
+	let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+
+	if let Some(exif) = &mut exif {
+		sanitize_exif(privacy, exif)?;
+		upright_exif(exif);
+	}
+
+	let mut image = DynamicImage::from_decoder(decoder).map_err(|err| {
+		debug!("Error decoding {:?}: {:?}", path, err);
+
+		unreadable(path)
+	})?;
+
+	image.apply_orientation(orientation);
+
+	if let Some(crop) = &config.crop {
+		image = crop.apply(image)?;
+	}
+
+	if let Some(max_long_edge) = config.max_long_edge {
+		if image.width().max(image.height()) > max_long_edge {
+			image = image.resize(max_long_edge, max_long_edge, FilterType::Lanczos3);
+		}
+	}
+
+	// Once the pixels are in sRGB, the original profile no longer describes
+	// them
+	let icc_profile = match (config.srgb, icc_profile) {
+		(true, Some(icc_profile)) => {
+			image = to_srgb(image, &icc_profile);
+
+			None
+		}
+		(_, icc_profile) => icc_profile,
+	};
+
+	if let Some(watermark) = &config.watermark {
+		image = watermark.apply(image)?;
+	}
+
+	let directory = tempfile::Builder::new()
+		.prefix("phupload-")
+		.tempdir()
+		.map_err(|err| {
+			debug!("Error creating a temporary directory: {:?}", err);
+
+			unwritable()
+		})?;
+	let stem = path.file_stem().unwrap_or_default();
+	let copy = directory
+		.path()
+		.join(Path::new(stem).with_extension(format.extension()));
+	let quality = config.quality.unwrap_or(DEFAULT_QUALITY).clamp(1, 100);
+
+	encode(
+		&image,
+		format,
+		quality,
+		Carried { icc_profile, exif },
+		&copy,
+	)?;
+
+	debug!("Uploading a processed copy of {:?} from {:?}", path, copy);
+
+	Ok(Derivative::Processed {
+		path: copy,
+		// Copies are always upright
+		dimensions: Dimensions {
+			width: image.width(),
+			height: image.height(),
+			orientation: 1,
+		},
+		_directory: directory,
+	})
+}
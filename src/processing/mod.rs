@@ -0,0 +1,4 @@
+pub(crate) mod crop;
+pub(crate) mod derivative;
+pub(crate) mod placeholder;
+pub(crate) mod watermark;
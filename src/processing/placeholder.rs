@@ -0,0 +1,159 @@
+use crate::UploadError;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader, RgbImage};
+use log::debug;
+use std::collections::HashMap;
+use std::path::Path;
+
+// BlurHash only keeps the broad shapes of a photo, so it's worked out from a
+// small copy
+const BLURHASH_SIZE: u32 = 64;
+// Wide enough to be recognizable when it's blurred up to size, while still
+// small enough to inline in a page
+const LQIP_SIZE: u32 = 16;
+const LQIP_QUALITY: u8 = 50;
+const PALETTE_SIZE: u32 = 64;
+const PALETTE_COLORS: usize = 5;
+// Colors closer than this are treated as the same color in the palette
+const PALETTE_DISTANCE: i32 = 48;
+
+// What a page can show while a photo is loading
+#[derive(Debug)]
+pub(crate) struct Placeholder {
+	pub(crate) blurhash: String,
+	// A tiny, blurry JPEG, as a data URL
+	pub(crate) lqip: String,
+	// The photo's most common colors, most common first, like `#4a6b8c`
+	pub(crate) palette: Vec<String>,
+}
+
+fn unreadable(path: &Path) -> UploadError {
+	UploadError::MalformedMetadata(Some(format!(
+		"Unable to read the image data in {}",
+		path.display()
+	)))
+}
+
+fn blurhash(image: &DynamicImage) -> Result<String, UploadError> {
+	let small = image
+		.resize(BLURHASH_SIZE, BLURHASH_SIZE, FilterType::Triangle)
+		.into_rgba8();
+	// More components along the longer side keep the shapes in proportion
+	let (x, y) = if small.width() >= small.height() {
+		(4, 3)
+	} else {
+		(3, 4)
+	};
+
+	blurhash::encode(x, y, small.width(), small.height(), small.as_raw()).map_err(|err| {
+		debug!("Error encoding the BlurHash: {:?}", err);
+
+		UploadError::UnknownError(Some("Unable to work out the photo's BlurHash".into()))
+	})
+}
+
+fn lqip(image: &DynamicImage) -> Result<String, UploadError> {
+	let small = image
+		.resize(LQIP_SIZE, LQIP_SIZE, FilterType::Triangle)
+		.into_rgb8();
+	let mut bytes = Vec::new();
+
+	JpegEncoder::new_with_quality(&mut bytes, LQIP_QUALITY)
+		.encode_image(&small)
+		.map_err(|err| {
+			debug!("Error encoding the LQIP: {:?}", err);
+
+			UploadError::UnknownError(Some("Unable to make the photo's placeholder".into()))
+		})?;
+
+	Ok(format!("data:image/jpeg;base64,{}", base64::encode(&bytes)))
+}
+
+// Group the pixels of a small copy into buckets of similar colors, and take
+// the average of the biggest buckets that aren't too close to one already
+// taken
+fn palette(image: &DynamicImage) -> Vec<String> {
+	let small: RgbImage = image
+		.resize(PALETTE_SIZE, PALETTE_SIZE, FilterType::Triangle)
+		.into_rgb8();
+	let mut buckets: HashMap<(u8, u8, u8), (u32, [u32; 3])> = HashMap::new();
+
+	for pixel in small.pixels() {
+		let [red, green, blue] = pixel.0;
+		let bucket = buckets
+			.entry((red >> 4, green >> 4, blue >> 4))
+			.or_insert((0, [0; 3]));
+
+		bucket.0 += 1;
+
+		for (total, channel) in bucket.1.iter_mut().zip(pixel.0.iter()) {
+			*total += u32::from(*channel);
+		}
+	}
+
+	let mut buckets: Vec<(u32, [i32; 3])> = buckets
+		.into_values()
+		.map(|(count, totals)| (count, totals.map(|total| (total / count) as i32)))
+		.collect();
+
+	buckets.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+	let mut colors: Vec<[i32; 3]> = Vec::new();
+
+	for (_, color) in buckets {
+		let distinct = colors.iter().all(|taken| {
+			taken
+				.iter()
+				.zip(color.iter())
+				.map(|(a, b)| (a - b).abs())
+				.sum::<i32>()
+				>= PALETTE_DISTANCE
+		});
+
+		if distinct {
+			colors.push(color);
+		}
+
+		if colors.len() == PALETTE_COLORS {
+			break;
+		}
+	}
+
+	colors
+		.iter()
+		.map(|[red, green, blue]| format!("#{:02x}{:02x}{:02x}", red, green, blue))
+		.collect()
+}
+
+// Work out the placeholders from the photo as it's shown, turned upright
+pub(crate) fn placeholder(path: &Path) -> Result<Placeholder, UploadError> {
+	let mut decoder = ImageReader::open(path)
+		.and_then(|reader| reader.with_guessed_format())
+		.map_err(|err| {
+			debug!("Error opening {:?}: {:?}", path, err);
+
+			unreadable(path)
+		})?
+		.into_decoder()
+		.map_err(|err| {
+			debug!("Error decoding {:?}: {:?}", path, err);
+
+			unreadable(path)
+		})?;
+	let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+	let mut image = DynamicImage::from_decoder(decoder).map_err(|err| {
+		debug!("Error decoding {:?}: {:?}", path, err);
+
+		unreadable(path)
+	})?;
+
+	image.apply_orientation(orientation);
+
+	Ok(Placeholder {
+		blurhash: blurhash(&image)?,
+		lqip: lqip(&image)?,
+		palette: palette(&image),
+	})
+}
@@ -0,0 +1,222 @@
+use crate::metadata::overrides::expand_home;
+use crate::UploadError;
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::imageops::{overlay, resize, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs::read;
+
+const DEFAULT_MARGIN: f64 = 0.02;
+const DEFAULT_OPACITY: f64 = 0.6;
+const DEFAULT_SCALE: f64 = 0.2;
+const DEFAULT_COLOR: &str = "#ffffff";
+// Text is measured at this size before being scaled to fit
+const MEASURE_SIZE: f32 = 100.0;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Position {
+	TopLeft,
+	Top,
+	TopRight,
+	Left,
+	Center,
+	Right,
+	BottomLeft,
+	Bottom,
+	#[default]
+	BottomRight,
+}
+
+// A logo or line of text drawn over the photo
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct WatermarkConfig {
+	// The path to a logo, usually a PNG with a transparent background
+	pub(crate) image: Option<String>,
+	pub(crate) text: Option<String>,
+	// The path to a TrueType or OpenType font, which text watermarks need
+	pub(crate) font: Option<String>,
+	// Like `#ffffff`, for text
+	pub(crate) color: Option<String>,
+	#[serde(default)]
+	pub(crate) position: Position,
+	// The space around the watermark, as a fraction of the photo's shorter side
+	pub(crate) margin: Option<f64>,
+	// From 0 for invisible to 1 for solid
+	pub(crate) opacity: Option<f64>,
+	// The watermark's width, as a fraction of the photo's width
+	pub(crate) scale: Option<f64>,
+}
+
+fn malformed(message: &str) -> UploadError {
+	UploadError::MalformedConfig(Some(format!("The watermark {}", message)))
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, UploadError> {
+	let path = expand_home(path);
+
+	read(&path).map_err(|err| {
+		debug!("Error reading {:?}: {:?}", path, err);
+
+		malformed(&format!("file {} couldn't be read", path.display()))
+	})
+}
+
+// `#rrggbb`, with or without the `#`
+pub(crate) fn color(value: &str) -> Option<Rgba<u8>> {
+	let hex = value.trim_start_matches('#');
+
+	if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+		return None;
+	}
+
+	let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+
+	Some(Rgba([channel(0)?, channel(2)?, channel(4)?, 255]))
+}
+
+// Draw a single line of text in `color`, on a transparent background
+fn render_text(font: &FontVec, text: &str, size: f32, color: Rgba<u8>) -> RgbaImage {
+	let scaled = font.as_scaled(PxScale::from(size));
+	let mut glyphs = Vec::new();
+	let mut caret = 0.0;
+	let mut previous = None;
+
+	for character in text.chars() {
+		let id = font.glyph_id(character);
+
+		if let Some(previous) = previous {
+			caret += scaled.kern(previous, id);
+		}
+
+		glyphs.push(id.with_scale_and_position(size, point(caret, scaled.ascent())));
+		caret += scaled.h_advance(id);
+		previous = Some(id);
+	}
+
+	let width = caret.ceil().max(1.0) as u32;
+	let height = (scaled.ascent() - scaled.descent()).ceil().max(1.0) as u32;
+	let mut image = RgbaImage::from_pixel(width, height, Rgba([color[0], color[1], color[2], 0]));
+
+	for glyph in glyphs {
+		if let Some(outlined) = font.outline_glyph(glyph) {
+			let bounds = outlined.px_bounds();
+
+			outlined.draw(|x, y, coverage| {
+				let x = x as i64 + bounds.min.x as i64;
+				let y = y as i64 + bounds.min.y as i64;
+
+				if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+					let pixel = image.get_pixel_mut(x as u32, y as u32);
+					let alpha = (coverage.clamp(0.0, 1.0) * 255.0) as u8;
+
+					pixel[3] = pixel[3].max(alpha);
+				}
+			});
+		}
+	}
+
+	image
+}
+
+impl WatermarkConfig {
+	// The watermark at the size it's drawn on a photo `width` pixels wide
+	fn mark(&self, width: u32) -> Result<RgbaImage, UploadError> {
+		let scale = self.scale.unwrap_or(DEFAULT_SCALE);
+
+		if !(scale > 0.0 && scale <= 1.0) {
+			return Err(malformed("scale should be more than 0, and at most 1"));
+		}
+
+		let target = ((f64::from(width) * scale).round() as u32).max(1);
+
+		match (&self.image, &self.text) {
+			(Some(path), None) => {
+				let logo = image::load_from_memory(&read_file(path)?)
+					.map_err(|err| {
+						debug!("Error decoding the watermark {}: {:?}", path, err);
+
+						malformed(&format!("image {} couldn't be read", path))
+					})?
+					.into_rgba8();
+				let height =
+					(u64::from(logo.height()) * u64::from(target) / u64::from(logo.width())).max(1) as u32;
+
+				Ok(resize(&logo, target, height, FilterType::Lanczos3))
+			}
+			(None, Some(text)) => {
+				let path = self
+					.font
+					.as_ref()
+					.ok_or_else(|| malformed("text needs a font"))?;
+				let font = FontVec::try_from_vec(read_file(path)?)
+					.map_err(|_| malformed(&format!("font {} isn't a font", path)))?;
+				let color = color(self.color.as_deref().unwrap_or(DEFAULT_COLOR))
+					.ok_or_else(|| malformed("color should be like #ffffff"))?;
+				let measured = render_text(&font, text, MEASURE_SIZE, color).width();
+				let size = MEASURE_SIZE * target as f32 / measured as f32;
+
+				Ok(render_text(&font, text, size, color))
+			}
+			_ => Err(malformed("needs either an image or text")),
+		}
+	}
+
+	// Where the top left of the watermark goes
+	fn origin(&self, photo: (u32, u32), mark: (u32, u32)) -> Result<(i64, i64), UploadError> {
+		let margin = self.margin.unwrap_or(DEFAULT_MARGIN);
+
+		if !(0.0..0.5).contains(&margin) {
+			return Err(malformed("margin should be from 0 to 0.5"));
+		}
+
+		let margin = (f64::from(photo.0.min(photo.1)) * margin).round() as i64;
+		let (width, height) = (i64::from(photo.0), i64::from(photo.1));
+		let (mark_width, mark_height) = (i64::from(mark.0), i64::from(mark.1));
+		let left = margin;
+		let center = (width - mark_width) / 2;
+		let right = width - mark_width - margin;
+		let top = margin;
+		let middle = (height - mark_height) / 2;
+		let bottom = height - mark_height - margin;
+
+		Ok(match self.position {
+			Position::TopLeft => (left, top),
+			Position::Top => (center, top),
+			Position::TopRight => (right, top),
+			Position::Left => (left, middle),
+			Position::Center => (center, middle),
+			Position::Right => (right, middle),
+			Position::BottomLeft => (left, bottom),
+			Position::Bottom => (center, bottom),
+			Position::BottomRight => (right, bottom),
+		})
+	}
+
+	pub(crate) fn apply(&self, image: DynamicImage) -> Result<DynamicImage, UploadError> {
+		let opacity = self.opacity.unwrap_or(DEFAULT_OPACITY);
+
+		if !(0.0..=1.0).contains(&opacity) {
+			return Err(malformed("opacity should be from 0 to 1"));
+		}
+
+		let mut mark = self.mark(image.width())?;
+
+		for pixel in mark.pixels_mut() {
+			pixel[3] = (f64::from(pixel[3]) * opacity).round() as u8;
+		}
+
+		let (x, y) = self.origin((image.width(), image.height()), mark.dimensions())?;
+		let has_alpha = image.color().has_alpha();
+		let mut photo = image.into_rgba8();
+
+		overlay(&mut photo, &mark, x, y);
+
+		Ok(if has_alpha {
+			DynamicImage::from(photo)
+		} else {
+			DynamicImage::from(DynamicImage::from(photo).into_rgb8())
+		})
+	}
+}
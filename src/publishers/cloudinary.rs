@@ -1,19 +1,36 @@
-use crate::metadata::config::PublisherConfig;
+use crate::metadata::config::{redact, PublisherConfig};
+use crate::metadata::identity::id;
+use crate::metadata::privacy::{location, PrivacyConfig};
+use crate::metadata::tagging::{destination_tags, TagsConfig};
+use crate::metadata::template::render;
+use crate::processing::derivative::{derive, ProcessingConfig};
 use crate::{PhotoDestination, Upload, UploadError};
 use log::{debug, info};
 use reqwest::header::HeaderValue;
 use reqwest::{multipart, Client};
 use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 use std::time::{Duration, SystemTime};
 
 pub(crate) struct Cloudinary;
 
+// The same for every config, since there can only be one
+pub(crate) const DESTINATION: &str = "cloudinary";
+
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct CloudinaryConfig {
 	pub(crate) cloud_name: String,
 	pub(crate) api_key: String,
 	pub(crate) api_secret: String,
+	// Rendered into the `caption` context instead of the title
+	pub(crate) caption_template: Option<String>,
+	pub(crate) privacy: Option<PrivacyConfig>,
+	pub(crate) tags: Option<TagsConfig>,
+	pub(crate) processing: Option<ProcessingConfig>,
+	// Rendered into the public id, defaulting to `{slug}-{short_hash}`
+	pub(crate) id_template: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -22,48 +39,45 @@ struct UploadResponse {
 	format: String,
 }
 
+#[derive(Deserialize, Debug)]
+struct DestroyResponse {
+	result: String,
+}
+
 impl PublisherConfig for CloudinaryConfig {}
 
 impl PhotoDestination for Cloudinary {
 	type Config = CloudinaryConfig;
 
-	fn upload(config: Self::Config, photo: &Upload) -> Result<String, UploadError> {
+	fn destination(_config: &Self::Config) -> String {
+		DESTINATION.into()
+	}
+
+	fn upload(config: &Self::Config, photo: &Upload) -> Result<String, UploadError> {
 		info!("Beginning upload to cloudinary...");
 
-		let public_id = photo.metadata.title.replace(" ", "-");
 		let client = Client::builder()
 			.timeout(Duration::from_secs(120))
 			.build()
 			.unwrap();
-		let timestamp = SystemTime::now()
-			.duration_since(SystemTime::UNIX_EPOCH)
-			.expect("System time is invalid")
-			.as_secs();
-		let upload_tags = photo.metadata.tags.join(",");
-		let cloudinary_params = format!(
-			"public_id={}&tags={}&timestamp={}{}",
-			&public_id, upload_tags, timestamp, config.api_secret
-		);
-		let signed_params = digest(&SHA1_FOR_LEGACY_USE_ONLY, cloudinary_params.as_bytes());
-		let signed_string = format!("{:?}", signed_params).replace("SHA1:", "");
-
-		match multipart::Form::new()
-			.text("api_key", config.api_key)
-			.text("timestamp", format!("{}", timestamp))
-			.text("public_id", public_id)
-			.text("tags", upload_tags)
-			.text("signature", signed_string)
-			.file("file", photo.path)
-		{
+		let mut form = multipart::Form::new();
+		let file = derive(
+			config.processing.as_ref(),
+			config.privacy.as_ref(),
+			&photo.path,
+		)?;
+
+		for (k, v) in Self::upload_params(config, photo)? {
+			form = form.text(k, v);
+		}
+
+		match form.file("file", file.path()) {
 			Ok(post_data) => {
 				debug!("Created post data");
 
 				let mut error_header = String::new();
 				let json = client
-					.post(&format!(
-						"https://api.cloudinary.com/v1_1/{}/image/upload",
-						config.cloud_name
-					))
+					.post(&Self::endpoint(config, "upload"))
 					.multipart(post_data)
 					.send()
 					.and_then(|mut cloudinary_res| {
@@ -88,7 +102,7 @@ impl PhotoDestination for Cloudinary {
 						Ok(format!("{}.{}", public_id, format))
 					}
 					Err(error) => {
-						let error_to_display = if error_header.len() > 0 {
+						let error_to_display = if !error_header.is_empty() {
 							error_header
 						} else {
 							format!("{:?}", error)
@@ -107,4 +121,193 @@ impl PhotoDestination for Cloudinary {
 			}
 		}
 	}
+
+	fn remove(config: &Self::Config, id: &str) -> Result<(), UploadError> {
+		info!("Deleting {} from cloudinary...", id);
+
+		let public_id = Self::id_to_public_id(id);
+		let response: DestroyResponse =
+			Self::post(config, "destroy", vec![("public_id", public_id.into())])?;
+
+		match response.result.as_str() {
+			"ok" => Ok(()),
+			"not found" => {
+				info!("{} was already gone from cloudinary", public_id);
+
+				Ok(())
+			}
+			result => Err(UploadError::BadGateway(Some(format!(
+				"Cloudinary couldn't delete {}: {}",
+				public_id, result
+			)))),
+		}
+	}
+
+	fn update(config: &Self::Config, id: &str, photo: &Upload) -> Result<String, UploadError> {
+		info!("Updating {} on cloudinary...", id);
+
+		let UploadResponse { public_id, format } = Self::post(
+			config,
+			"explicit",
+			vec![
+				("public_id", Self::id_to_public_id(id).into()),
+				("type", "upload".into()),
+				("tags", Self::tags(config, photo)),
+				("context", Self::context(config, photo)?),
+			],
+		)?;
+
+		Ok(format!("{}.{}", public_id, format))
+	}
+
+	fn preview(config: &Self::Config, photo: &Upload) -> Result<Value, UploadError> {
+		let params: Map<String, Value> = Self::upload_params(config, photo)?
+			.into_iter()
+			.map(|(k, v)| match k {
+				"api_key" => (k.into(), Value::String(redact(&v))),
+				_ => (k.into(), Value::String(v)),
+			})
+			.collect();
+
+		Ok(json!({
+			"endpoint": Self::endpoint(config, "upload"),
+			"file": photo.path,
+			"params": params,
+			"privacy": config.privacy,
+			"processing": config.processing,
+		}))
+	}
+}
+
+impl Cloudinary {
+	fn endpoint(config: &CloudinaryConfig, action: &str) -> String {
+		format!(
+			"https://api.cloudinary.com/v1_1/{}/image/{}",
+			config.cloud_name, action
+		)
+	}
+
+	// Cloudinary signs requests with a SHA-1 of the alphabetically sorted
+	// params followed by the api secret
+	fn signature(config: &CloudinaryConfig, params: &mut Vec<(&'static str, String)>) -> String {
+		params.sort_by(|a, b| a.0.cmp(b.0));
+
+		let param_queries: Vec<String> = params.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+		let to_sign = format!("{}{}", param_queries.join("&"), config.api_secret);
+		let signed_params = digest(&SHA1_FOR_LEGACY_USE_ONLY, to_sign.as_bytes());
+
+		format!("{:?}", signed_params).replace("SHA1:", "")
+	}
+
+	fn timestamp() -> String {
+		SystemTime::now()
+			.duration_since(SystemTime::UNIX_EPOCH)
+			.expect("System time is invalid")
+			.as_secs()
+			.to_string()
+	}
+
+	// The ledger keeps `<public_id>.<format>`, but the admin API only wants the
+	// public id
+	fn id_to_public_id(id: &str) -> &str {
+		id.rsplit_once('.')
+			.map(|(public_id, _)| public_id)
+			.unwrap_or(id)
+	}
+
+	// Tags are separated by commas, so they can't have any of their own
+	fn tags(config: &CloudinaryConfig, photo: &Upload) -> String {
+		destination_tags(config.tags.as_ref(), &photo.metadata)
+			.iter()
+			.map(|tag| tag.replace(',', " "))
+			.collect::<Vec<String>>()
+			.join(",")
+	}
+
+	pub(crate) fn public_id(
+		config: &CloudinaryConfig,
+		photo: &Upload,
+	) -> Result<String, UploadError> {
+		id(config.id_template.as_deref(), photo)
+	}
+
+	// The caption and description, as contextual metadata. `=` and `|` separate
+	// the entries, so they need to be escaped in the values
+	fn context(config: &CloudinaryConfig, photo: &Upload) -> Result<String, UploadError> {
+		let escape = |value: &str| value.replace('=', "\\=").replace('|', "\\|");
+		let caption = match &config.caption_template {
+			Some(template) => render(template, &photo.metadata)?,
+			None => photo.metadata.title.clone(),
+		};
+		let mut context: Vec<String> = Vec::new();
+
+		if !caption.is_empty() {
+			context.push(format!("caption={}", escape(&caption)));
+		}
+
+		if !photo.metadata.description.is_empty() {
+			context.push(format!("alt={}", escape(&photo.metadata.description)));
+		}
+
+		if let Some(location) = location(config.privacy.as_ref(), &photo.metadata) {
+			context.push(format!("latitude={}", location.latitude));
+			context.push(format!("longitude={}", location.longitude));
+			context.push(format!("location={}", escape(&location.name())));
+		}
+
+		Ok(context.join("|"))
+	}
+
+	// Add the timestamp, api key, and signature to a request's params. Empty
+	// params are dropped, since they're not part of the signature
+	fn signed_params(
+		config: &CloudinaryConfig,
+		params: Vec<(&'static str, String)>,
+	) -> Vec<(&'static str, String)> {
+		let mut params: Vec<(&'static str, String)> =
+			params.into_iter().filter(|(_, v)| !v.is_empty()).collect();
+
+		params.push(("timestamp", Self::timestamp()));
+
+		let signature = Self::signature(config, &mut params);
+
+		params.extend(vec![
+			("api_key", config.api_key.clone()),
+			("signature", signature),
+		]);
+
+		params
+	}
+
+	fn upload_params(
+		config: &CloudinaryConfig,
+		photo: &Upload,
+	) -> Result<Vec<(&'static str, String)>, UploadError> {
+		Ok(Self::signed_params(
+			config,
+			vec![
+				("public_id", Self::public_id(config, photo)?),
+				("tags", Self::tags(config, photo)),
+				("context", Self::context(config, photo)?),
+			],
+		))
+	}
+
+	// Send a signed request to the admin API and parse the JSON response
+	fn post<T: DeserializeOwned>(
+		config: &CloudinaryConfig,
+		action: &str,
+		params: Vec<(&'static str, String)>,
+	) -> Result<T, UploadError> {
+		Client::new()
+			.post(&Self::endpoint(config, action))
+			.form(&Self::signed_params(config, params))
+			.send()
+			.and_then(|mut res| res.json())
+			.map_err(|err| {
+				debug!("Error calling {} on cloudinary: {:?}", action, err);
+
+				UploadError::BadGateway(Some(format!("Error calling {} on cloudinary", action)))
+			})
+	}
 }
@@ -1,11 +1,16 @@
-use crate::metadata::config::{read_config, write_config, Config, PublisherConfig};
+use crate::metadata::config::{read_config, redact, write_config, Config, PublisherConfig};
+use crate::metadata::privacy::{location, LocationPolicy, PrivacyConfig};
+use crate::metadata::tagging::{destination_tags, TagsConfig};
+use crate::metadata::template::render;
+use crate::processing::derivative::{derive, ProcessingConfig};
 use crate::{PhotoDestination, Upload, UploadError};
-use base64;
-use log::{debug, info};
+use log::{debug, info, warn};
 use rand::{thread_rng, Rng};
+use regex::Regex;
 use reqwest::{multipart, Client};
 use ring::hmac::{sign, Key, HMAC_SHA1_FOR_LEGACY_USE_ONLY};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Write};
 use std::net::TcpListener;
@@ -13,9 +18,10 @@ use std::time::{Duration, SystemTime};
 use url::Url;
 use urlencoding::encode;
 
-const FLICKR_API_URL: &'static str = "https://www.flickr.com/services";
-const FLICKR_UPLOAD_URL: &'static str = "https://up.flickr.com/services/upload/";
-const FLICKR_CALLBACK_URL: &'static str = "http://localhost:8282";
+const FLICKR_API_URL: &str = "https://www.flickr.com/services";
+const FLICKR_UPLOAD_URL: &str = "https://up.flickr.com/services/upload/";
+const FLICKR_REST_URL: &str = "https://api.flickr.com/services/rest/";
+const FLICKR_CALLBACK_URL: &str = "http://localhost:8282";
 
 pub(crate) struct Flickr;
 
@@ -30,6 +36,11 @@ pub(crate) struct FlickrConfig {
 	pub(crate) oauth_verifier: Option<String>,
 	pub(crate) oauth_access_token: Option<String>,
 	pub(crate) oauth_access_token_secret: Option<String>,
+	// Rendered into the description instead of the photo's own description
+	pub(crate) caption_template: Option<String>,
+	pub(crate) privacy: Option<PrivacyConfig>,
+	pub(crate) tags: Option<TagsConfig>,
+	pub(crate) processing: Option<ProcessingConfig>,
 }
 
 impl PublisherConfig for FlickrConfig {}
@@ -68,10 +79,10 @@ impl Oauth {
 		path: String,
 		params: &mut Vec<(&str, &str)>,
 	) -> String {
-		params.sort_by(|a, b| a.0.cmp(&b.0));
+		params.sort_by(|a, b| a.0.cmp(b.0));
 
 		let param_queries: Vec<String> = params
-			.into_iter()
+			.iter()
 			.map(|(k, v)| format!("{}={}", k, encode(v)))
 			.collect();
 		let param_query = param_queries.join("&");
@@ -146,55 +157,52 @@ impl Oauth {
 
 	fn authorize_app(config: FlickrConfig) -> Result<FlickrConfig, UploadError> {
 		let addr = "0.0.0.0:8282";
-		let mut oauth_verifier = String::new();
 		let listener = TcpListener::bind(addr).unwrap();
 
 		info!(
-			"Please open {}/oauth/authorize?oauth_token={}&perms=write",
+			"Please open {}/oauth/authorize?oauth_token={}&perms=delete",
 			FLICKR_API_URL,
 			config.oauth_token.to_owned().unwrap()
 		);
 
-		for stream in listener.incoming() {
-			match stream {
-				Ok(mut stream) => {
-					{
-						let mut reader = BufReader::new(&stream);
-						let mut request_line = String::new();
+		let oauth_verifier: String = match listener.accept() {
+			Ok((mut stream, _)) => {
+				let oauth_verifier = {
+					let mut reader = BufReader::new(&stream);
+					let mut request_line = String::new();
 
-						reader.read_line(&mut request_line).unwrap();
+					reader.read_line(&mut request_line).unwrap();
 
-						let redirect_url = request_line.split_whitespace().nth(1).unwrap();
-						let full_url = format!("{}{}", FLICKR_CALLBACK_URL, redirect_url);
-						let url = Url::parse(&full_url).unwrap();
-						let (_, verifier) = url
-							.query_pairs()
-							.find(|(k, _)| k == "oauth_verifier")
-							.unwrap();
+					let redirect_url = request_line.split_whitespace().nth(1).unwrap();
+					let full_url = format!("{}{}", FLICKR_CALLBACK_URL, redirect_url);
+					let url = Url::parse(&full_url).unwrap();
+					let (_, verifier) = url
+						.query_pairs()
+						.find(|(k, _)| k == "oauth_verifier")
+						.unwrap();
 
-						oauth_verifier = verifier.into();
-					}
+					verifier.into()
+				};
 
-					let message = "Go back to your terminal";
-					let response = format!(
-						"HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
-						message.len(),
-						message
-					);
+				let message = "Go back to your terminal";
+				let response = format!(
+					"HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+					message.len(),
+					message
+				);
 
-					stream.write_all(response.as_bytes()).unwrap();
+				stream.write_all(response.as_bytes()).unwrap();
 
-					break;
-				}
-				Err(err) => {
-					debug!("Error starting flickr auth server: {:?}", err);
+				oauth_verifier
+			}
+			Err(err) => {
+				debug!("Error starting flickr auth server: {:?}", err);
 
-					return Err(UploadError::BadGateway(Some(
-						"Error starting flickr auth server. Please try again.".into(),
-					)));
-				}
+				return Err(UploadError::BadGateway(Some(
+					"Error starting flickr auth server. Please try again.".into(),
+				)));
 			}
-		}
+		};
 
 		Ok(FlickrConfig {
 			oauth_verifier: Some(oauth_verifier),
@@ -208,13 +216,13 @@ impl Oauth {
 		let timestamp = Oauth::timestamp();
 		let nonce = Oauth::nonce();
 		let secret = &config.oauth_token_secret.clone().unwrap();
-		let key = Oauth::key(&config, Some(&secret.as_str()));
+		let key = Oauth::key(&config, Some(secret.as_str()));
 		let verifier = &config.oauth_verifier.clone().unwrap();
 		let token = &config.oauth_token.clone().unwrap();
 		let mut params = vec![
 			("oauth_nonce", nonce.as_str()),
 			("oauth_timestamp", timestamp.as_str()),
-			("oauth_consumer_key", &config.oauth_client_key.as_str()),
+			("oauth_consumer_key", config.oauth_client_key.as_str()),
 			("oauth_version", "1.0"),
 			("oauth_signature_method", "HMAC-SHA1"),
 			("oauth_callback", FLICKR_CALLBACK_URL),
@@ -266,88 +274,371 @@ impl Oauth {
 impl PhotoDestination for Flickr {
 	type Config = FlickrConfig;
 
-	fn upload(config: Self::Config, photo: &Upload) -> Result<String, UploadError> {
+	fn destination(_config: &Self::Config) -> String {
+		"flickr".into()
+	}
+
+	fn upload(config: &Self::Config, photo: &Upload) -> Result<String, UploadError> {
 		info!("Beginning upload to Flickr...");
 
-		let auth_config = match config.oauth_access_token {
-			Some(_) => config,
+		let client = Client::builder()
+			.timeout(Duration::from_secs(120))
+			.build()
+			.unwrap();
+		let params = Self::upload_params(config, photo)?;
+		let file = derive(
+			config.processing.as_ref(),
+			config.privacy.as_ref(),
+			&photo.path,
+		)?;
+
+		let mut body = multipart::Form::new();
+
+		for (k, v) in params {
+			body = body.text(k, v);
+		}
+
+		let response = client
+			.post(FLICKR_UPLOAD_URL)
+			.multipart(body.file("photo", file.path()).unwrap())
+			.send()
+			.and_then(|mut res| res.text())
+			.map_err(|err| {
+				debug!("Error publishing the photo to Flickr: {:?}", err);
+
+				UploadError::BadGateway(Some("Error publishing the photo to Flickr".into()))
+			})?;
+
+		debug!("flickr response: {:?}", response);
+
+		let id = Self::photo_id(&response)?;
+
+		// The photo is already up, so a missing location shouldn't make it look
+		// like the upload failed
+		if let Err(err) = Self::set_location(config, &id, photo) {
+			warn!("Couldn't set the location of {} on Flickr: {}", id, err);
+		}
+
+		if let Err(err) = Self::set_date_taken(config, &id, photo) {
+			warn!("Couldn't set when {} was taken on Flickr: {}", id, err);
+		}
+
+		Ok(id)
+	}
+
+	fn remove(config: &Self::Config, id: &str) -> Result<(), UploadError> {
+		info!("Deleting photo {} from Flickr...", id);
+
+		Self::call(config, "flickr.photos.delete", &[("photo_id", id)])?;
+
+		Ok(())
+	}
+
+	fn update(config: &Self::Config, id: &str, photo: &Upload) -> Result<String, UploadError> {
+		info!("Updating photo {} on Flickr...", id);
+
+		let tags = Self::tags(config, photo);
+		let description = Self::description(config, photo)?;
+
+		Self::call(
+			config,
+			"flickr.photos.setMeta",
+			&[
+				("photo_id", id),
+				("title", photo.metadata.title.as_str()),
+				("description", description.as_str()),
+			],
+		)?;
+		Self::call(
+			config,
+			"flickr.photos.setTags",
+			&[("photo_id", id), ("tags", tags.as_str())],
+		)?;
+		Self::set_location(config, id, photo)?;
+		Self::set_date_taken(config, id, photo)?;
+
+		Ok(id.into())
+	}
+
+	fn preview(config: &Self::Config, photo: &Upload) -> Result<Value, UploadError> {
+		if config.oauth_access_token.is_none() {
+			return Ok(json!({
+				"endpoint": FLICKR_UPLOAD_URL,
+				"file": photo.path,
+				"note": "No access token is saved yet, so the app would be authorized before uploading",
+			}));
+		}
+
+		let params: Map<String, Value> = Self::upload_params(config, photo)?
+			.into_iter()
+			.map(|(k, v)| match k {
+				"oauth_consumer_key" | "oauth_token" => (k.into(), Value::String(redact(&v))),
+				_ => (k.into(), Value::String(v)),
+			})
+			.collect();
+
+		Ok(json!({
+			"endpoint": FLICKR_UPLOAD_URL,
+			"file": photo.path,
+			"params": params,
+			"location": Self::location_params(config, photo)
+				.map(|params| params.into_iter().collect::<HashMap<&str, String>>()),
+			"privacy": config.privacy,
+			"processing": config.processing,
+			"date_taken": Self::date_taken(photo),
+		}))
+	}
+}
+
+impl Flickr {
+	// Run the OAuth flow once, up front, so a batch of photos shares a single
+	// access token instead of asking to authorize the app for every photo
+	pub(crate) fn authenticate(config: FlickrConfig) -> Result<FlickrConfig, UploadError> {
+		match config.oauth_access_token {
+			Some(_) => Ok(config),
 			None => {
 				let token_config = Oauth::get_request_token(config)?;
 				let verifier_config = Oauth::authorize_app(token_config)?;
 				let access_config = Oauth::get_access_token(verifier_config)?;
-				let final_config = Self::save_access_token(access_config)?;
 
-				final_config
+				Self::save_access_token(access_config)
+			}
+		}
+	}
+
+	// Authenticate the config in place before a batch. A failure only fails
+	// flickr, so the error is returned to be reported for every photo while the
+	// other destinations go ahead
+	pub(crate) fn authenticate_batch(config: &mut Option<FlickrConfig>) -> Option<UploadError> {
+		let flickr_config = config.as_ref()?.clone();
+
+		match Self::authenticate(flickr_config) {
+			Ok(flickr_config) => {
+				*config = Some(flickr_config);
+
+				None
 			}
+			Err(err) => Some(err),
+		}
+	}
+
+	fn save_access_token(config: FlickrConfig) -> Result<FlickrConfig, UploadError> {
+		let existing_config = read_config()?;
+		let new_config = Config {
+			flickr: Some(config.clone()),
+			..existing_config
 		};
-		let client = Client::builder()
-			.timeout(Duration::from_secs(120))
-			.build()
-			.unwrap();
+
+		write_config(new_config)?;
+
+		Ok(config)
+	}
+
+	// Sign a request to the API with the saved access token. The OAuth params
+	// are added to the ones passed in
+	fn signed_params(
+		config: &FlickrConfig,
+		method: &str,
+		url: &str,
+		request_params: &[(&'static str, &str)],
+	) -> Vec<(&'static str, String)> {
 		let timestamp = Oauth::timestamp();
 		let nonce = Oauth::nonce();
 		let key = Oauth::key(
-			&auth_config,
+			config,
 			Some(
-				&auth_config
+				config
 					.oauth_access_token_secret
 					.to_owned()
 					.unwrap()
 					.as_str(),
 			),
 		);
-		let token = auth_config.oauth_access_token.clone().unwrap();
-		let tags = photo.metadata.tags.join(" ");
-		let title = &photo.metadata.title;
-		let description = &photo.metadata.description;
+		let token = config.oauth_access_token.clone().unwrap();
 		let mut params = vec![
 			("oauth_nonce", nonce.as_str()),
 			("oauth_timestamp", timestamp.as_str()),
-			("oauth_consumer_key", auth_config.oauth_client_key.as_str()),
+			("oauth_consumer_key", config.oauth_client_key.as_str()),
 			("oauth_version", "1.0"),
 			("oauth_signature_method", "HMAC-SHA1"),
 			("oauth_token", token.as_str()),
-			("format", "json"),
-			("title", title.as_str()),
-			("description", description.as_str()),
-			("tags", tags.as_str()),
-			("content_type", "1"),
 		];
-		let signature = Oauth::create_signature(&key, "POST", FLICKR_UPLOAD_URL.into(), &mut params);
+
+		params.extend(request_params);
+
+		let signature = Oauth::create_signature(&key, method, url.into(), &mut params);
 
 		params.extend(&[("oauth_signature", signature.as_str())]);
 
-		let mut body = multipart::Form::new();
+		params
+			.into_iter()
+			.map(|(k, v)| (k, String::from(v)))
+			.collect()
+	}
 
-		for (k, v) in params {
-			body = body.text(k, String::from(v));
+	fn description(config: &FlickrConfig, photo: &Upload) -> Result<String, UploadError> {
+		match &config.caption_template {
+			Some(template) => render(template, &photo.metadata),
+			None => Ok(photo.metadata.description.clone()),
 		}
+	}
 
-		client
-			.post(FLICKR_UPLOAD_URL)
-			.multipart(body.file("photo", photo.path).unwrap())
-			.send()
-			.and_then(|mut res| res.text())
-			.map_err(|err| {
-				debug!("Error publishing the photo to Flickr: {:?}", err);
+	// Flickr separates tags with spaces, so tags with more than one word are
+	// quoted
+	fn tags(config: &FlickrConfig, photo: &Upload) -> String {
+		destination_tags(config.tags.as_ref(), &photo.metadata)
+			.iter()
+			.map(|tag| tag.replace('"', ""))
+			.map(|tag| {
+				if tag.contains(' ') {
+					format!("\"{}\"", tag)
+				} else {
+					tag
+				}
+			})
+			.collect::<Vec<String>>()
+			.join(" ")
+	}
 
-				UploadError::BadGateway(Some("Error publishing the photo to Flickr".into()))
-			})?;
+	// The signed OAuth params sent along with the photo
+	fn upload_params(
+		config: &FlickrConfig,
+		photo: &Upload,
+	) -> Result<Vec<(&'static str, String)>, UploadError> {
+		let tags = Self::tags(config, photo);
+		let description = Self::description(config, photo)?;
+
+		Ok(Self::signed_params(
+			config,
+			"POST",
+			FLICKR_UPLOAD_URL,
+			&[
+				("format", "json"),
+				("title", photo.metadata.title.as_str()),
+				("description", description.as_str()),
+				("tags", tags.as_str()),
+				("content_type", "1"),
+			],
+		))
+	}
+
+	// Flickr's accuracy ranges from 1 for the world to 16 for a street, so
+	// rounded locations are placed about as precisely as they were rounded
+	fn location_params(config: &FlickrConfig, photo: &Upload) -> Option<Vec<(&'static str, String)>> {
+		let location = location(config.privacy.as_ref(), &photo.metadata)?;
+		let accuracy = match &config.privacy {
+			Some(privacy) if privacy.location == LocationPolicy::Round => match privacy.round_km() {
+				km if km <= 1.0 => "15",
+				km if km <= 5.0 => "13",
+				km if km <= 25.0 => "11",
+				km if km <= 100.0 => "8",
+				_ => "6",
+			},
+			_ => "16",
+		};
 
-		Ok("".into())
+		Some(vec![
+			("lat", location.latitude.to_string()),
+			("lon", location.longitude.to_string()),
+			("accuracy", accuracy.into()),
+		])
 	}
-}
 
-impl Flickr {
-	fn save_access_token(config: FlickrConfig) -> Result<FlickrConfig, UploadError> {
-		let existing_config = read_config()?;
-		let new_config = Config {
-			flickr: Some(config.clone()),
-			..existing_config
+	fn set_location(config: &FlickrConfig, id: &str, photo: &Upload) -> Result<(), UploadError> {
+		let params = match Self::location_params(config, photo) {
+			Some(params) => params,
+			None => return Ok(()),
 		};
+		let mut request_params: Vec<(&'static str, &str)> = vec![("photo_id", id)];
 
-		write_config(new_config)?;
+		request_params.extend(params.iter().map(|(k, v)| (*k, v.as_str())));
 
-		Ok(config)
+		Self::call(config, "flickr.photos.geo.setLocation", &request_params)?;
+
+		Ok(())
+	}
+
+	// Flickr shows dates as they were on the camera's clock, without an offset
+	fn date_taken(photo: &Upload) -> String {
+		photo
+			.metadata
+			.created_at
+			.format("%Y-%m-%d %H:%M:%S")
+			.to_string()
+	}
+
+	fn set_date_taken(config: &FlickrConfig, id: &str, photo: &Upload) -> Result<(), UploadError> {
+		let date_taken = Self::date_taken(photo);
+
+		Self::call(
+			config,
+			"flickr.photos.setDates",
+			&[
+				("photo_id", id),
+				("date_taken", date_taken.as_str()),
+				("date_taken_granularity", "0"),
+			],
+		)?;
+
+		Ok(())
+	}
+
+	// Call a method of the REST API, and return its response if it succeeded
+	fn call(
+		config: &FlickrConfig,
+		method: &'static str,
+		request_params: &[(&'static str, &str)],
+	) -> Result<Value, UploadError> {
+		let mut params = vec![
+			("method", method),
+			("format", "json"),
+			("nojsoncallback", "1"),
+		];
+
+		params.extend(request_params);
+
+		let response: Value = Client::new()
+			.post(FLICKR_REST_URL)
+			.form(&Self::signed_params(
+				config,
+				"POST",
+				FLICKR_REST_URL,
+				&params,
+			))
+			.send()
+			.and_then(|mut res| res.json())
+			.map_err(|err| {
+				debug!("Error calling {} on Flickr: {:?}", method, err);
+
+				UploadError::BadGateway(Some(format!("Error calling {} on Flickr", method)))
+			})?;
+
+		debug!("flickr response: {:?}", response);
+
+		match response["stat"].as_str() {
+			Some("ok") => Ok(response),
+			_ => Err(UploadError::BadGateway(Some(format!(
+				"Flickr rejected {}: {}",
+				method,
+				response["message"].as_str().unwrap_or("unknown error")
+			)))),
+		}
+	}
+
+	// The upload API always responds with XML, whatever `format` is set to
+	fn photo_id(response: &str) -> Result<String, UploadError> {
+		let photo_id = Regex::new("<photoid>(?P<id>[^<]+)</photoid>").unwrap();
+		let error = Regex::new("<err [^>]*msg=\"(?P<msg>[^\"]*)\"").unwrap();
+
+		match (photo_id.captures(response), error.captures(response)) {
+			(Some(captures), _) => Ok(captures["id"].into()),
+			(None, Some(captures)) => Err(UploadError::BadGateway(Some(format!(
+				"Flickr rejected the photo: {}",
+				&captures["msg"]
+			)))),
+			(None, None) => Err(UploadError::BadGateway(Some(
+				"Flickr didn't respond with a photo id".into(),
+			))),
+		}
 	}
 }
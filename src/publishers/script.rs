@@ -1,8 +1,14 @@
 use crate::metadata::config::PublisherConfig;
+use crate::metadata::dimensions::height_at;
+use crate::metadata::identity::id;
+use crate::metadata::privacy::{location, PrivacyConfig};
+use crate::metadata::tagging::{destination_tags, hashtag, TagsConfig};
+use crate::processing::derivative::{derive, Derivative, ProcessingConfig};
+use crate::processing::placeholder::placeholder;
 use crate::{PhotoDestination, Upload, UploadError};
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
-use serde_json::{json, to_string};
+use serde_json::{json, to_string, Map, Value};
 use std::path::Path;
 use std::process::Command;
 
@@ -11,6 +17,17 @@ pub(crate) struct Script;
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct ScriptConfig {
 	pub(crate) path: String,
+	// Scripts are only sent a copy of the photo when they have processing
+	// settings, so otherwise only the location policy applies
+	pub(crate) privacy: Option<PrivacyConfig>,
+	pub(crate) tags: Option<TagsConfig>,
+	// Widths the script lays photos out at, sent with the height the photo
+	// would be at each
+	pub(crate) widths: Option<Vec<u32>>,
+	pub(crate) processing: Option<ProcessingConfig>,
+	// Rendered into the `key` sent to the script, defaulting to
+	// `{slug}-{short_hash}`
+	pub(crate) id_template: Option<String>,
 }
 
 impl PublisherConfig for ScriptConfig {}
@@ -18,25 +35,82 @@ impl PublisherConfig for ScriptConfig {}
 impl PhotoDestination for Script {
 	type Config = ScriptConfig;
 
-	fn upload(config: Self::Config, photo: &Upload) -> Result<String, UploadError> {
+	fn destination(config: &Self::Config) -> String {
+		format!("script:{}", config.path)
+	}
+
+	fn upload(config: &Self::Config, photo: &Upload) -> Result<String, UploadError> {
 		info!("Beginning custom script...");
 
-		let data = json!({
-			"url": photo.url.clone().unwrap_or("".into()),
-			"name": photo.metadata.title,
-			"description": photo.metadata.description,
-			"heightAt1200": photo.metadata.height_at_1200,
-			"camera": photo.metadata.camera,
-			"focalLength": photo.metadata.focal_length,
-			"iso": photo.metadata.iso,
-			"aperture": photo.metadata.aperture,
-			"shutterSpeed": photo.metadata.shutter_speed,
-			"createdAt": photo.metadata.created_at,
-			"tags": photo.metadata.tags
-		});
+		// The copy is deleted once the script is done with it
+		let file = Self::copy(config, photo)?;
+		let mut data = Self::payload(config, photo, "publish", file.as_ref())?;
+
+		Self::add_placeholders(&mut data, photo, file.as_ref());
+
+		if let Some(file) = &file {
+			data["file"] = json!(file.path());
+		}
+
+		Self::run(config, &data)
+	}
+
+	fn update(config: &Self::Config, id: &str, photo: &Upload) -> Result<String, UploadError> {
+		info!("Running custom script to update {}...", id);
+
+		// The copy is only made again so the size and placeholders match the
+		// published photo, and isn't sent
+		let file = Self::copy(config, photo)?;
+		let mut data = Self::payload(config, photo, "update", file.as_ref())?;
+
+		Self::add_placeholders(&mut data, photo, file.as_ref());
+		data["id"] = json!(id);
+
+		// Without a URL, the script keeps the one it already has
+		if photo.url.is_none() {
+			if let Some(data) = data.as_object_mut() {
+				data.remove("url");
+			}
+		}
+
+		// Scripts that don't print anything keep the id they already had
+		match Self::run(config, &data)? {
+			ref new_id if new_id.is_empty() => Ok(id.into()),
+			new_id => Ok(new_id),
+		}
+	}
 
+	fn remove(config: &Self::Config, id: &str) -> Result<(), UploadError> {
+		info!("Running custom script to remove {}...", id);
+
+		Self::run(
+			config,
+			&json!({
+				"action": "remove",
+				"id": id,
+			}),
+		)?;
+
+		Ok(())
+	}
+
+	// Dry runs don't read the image data, so the placeholders are left out and
+	// photos with processing settings are described by the original's size
+	fn preview(config: &Self::Config, photo: &Upload) -> Result<Value, UploadError> {
+		Ok(json!({
+			"script": config.path,
+			"payload": Self::payload(config, photo, "publish", None)?,
+			"processing": config.processing,
+		}))
+	}
+}
+
+impl Script {
+	// Run the script with its JSON as the only argument. The `action` field
+	// tells the script what's being asked of it
+	fn run(config: &ScriptConfig, data: &Value) -> Result<String, UploadError> {
 		let result = Command::new(&config.path)
-			.arg(to_string(&data).unwrap())
+			.arg(to_string(data).unwrap())
 			.current_dir(Path::new(&config.path).parent().unwrap())
 			.output()
 			.map_err(|err| {
@@ -49,21 +123,118 @@ impl PhotoDestination for Script {
 
 		match result.status.code() {
 			Some(0) => {
-				debug!(
-					"Successfully executed a script: {:?}",
-					String::from_utf8(result.stdout)
-				);
+				let output = String::from_utf8_lossy(&result.stdout);
+
+				debug!("Successfully executed a script: {:?}", output);
 
-				Ok("".into())
+				// Whatever the script prints is kept as its id for the photo
+				Ok(output.trim().into())
 			}
 			_ => {
 				let output = String::from_utf8(result.stderr);
 				debug!("Error executing custom script: {:?}", output);
 
-				Err(UploadError::UnknownError(Some(
-					format!("Error executing a custom script: {:?}", output).into(),
-				)))
+				Err(UploadError::UnknownError(Some(format!(
+					"Error executing a custom script: {:?}",
+					output
+				))))
 			}
 		}
 	}
+
+	// The copy of the photo a script with processing settings is sent
+	fn copy(config: &ScriptConfig, photo: &Upload) -> Result<Option<Derivative>, UploadError> {
+		config
+			.processing
+			.as_ref()
+			.map(|processing| derive(Some(processing), config.privacy.as_ref(), &photo.path))
+			.transpose()
+	}
+
+	// The placeholders are made from the processed copy when there is one, so
+	// they match the photo the script shows. Photos the image reader doesn't
+	// understand, like RAW files, are still published without them
+	fn add_placeholders(data: &mut Value, photo: &Upload, copy: Option<&Derivative>) {
+		let image = copy
+			.map(|copy| copy.path())
+			.unwrap_or_else(|| Path::new(&photo.path));
+		let placeholder = placeholder(image)
+			.map_err(|err| debug!("No placeholder for {:?}: {}", image, err))
+			.ok();
+
+		data["blurhash"] = json!(placeholder
+			.as_ref()
+			.map(|placeholder| &placeholder.blurhash));
+		data["lqip"] = json!(placeholder.as_ref().map(|placeholder| &placeholder.lqip));
+		data["palette"] = json!(placeholder.as_ref().map(|placeholder| &placeholder.palette));
+	}
+
+	// The JSON passed to custom scripts when publishing or updating a photo.
+	// When the script is sent a processed copy, its size is the copy's, since
+	// that's the photo the script shows
+	fn payload(
+		config: &ScriptConfig,
+		photo: &Upload,
+		action: &str,
+		copy: Option<&Derivative>,
+	) -> Result<Value, UploadError> {
+		let tags = destination_tags(config.tags.as_ref(), &photo.metadata);
+		let hashtags: Vec<String> = tags.iter().map(|tag| hashtag(tag)).collect();
+		let dimensions = copy
+			.and_then(|copy| copy.dimensions())
+			.or(photo.metadata.dimensions.as_ref());
+		let heights: Map<String, Value> = config
+			.widths
+			.iter()
+			.flatten()
+			.map(|width| (width.to_string(), json!(height_at(dimensions, *width))))
+			.collect();
+
+		Ok(json!({
+			"action": action,
+			"key": id(config.id_template.as_deref(), photo)?,
+			"hash": photo.hash,
+			"url": photo.url.clone().unwrap_or_default(),
+			"name": photo.metadata.title,
+			"description": photo.metadata.description,
+			"width": dimensions.map(|dimensions| dimensions.display_width()),
+			"height": dimensions.map(|dimensions| dimensions.display_height()),
+			"storedWidth": dimensions.map(|dimensions| dimensions.width),
+			"storedHeight": dimensions.map(|dimensions| dimensions.height),
+			"orientation": dimensions.map(|dimensions| dimensions.orientation),
+			"aspectRatio": dimensions.map(|dimensions| dimensions.aspect_ratio()),
+			// Kept for scripts written before `heights`
+			"heightAt1200": height_at(dimensions, 1200),
+			"heights": heights,
+			"camera": photo.metadata.camera,
+			// Kept as text for scripts written before `exposure`
+			"focalLength": photo.metadata.exposure.focal_length(),
+			"iso": photo.metadata.exposure.iso(),
+			"aperture": photo.metadata.exposure.aperture(),
+			"shutterSpeed": photo.metadata.exposure.shutter_speed,
+			"exposureTime": photo
+				.metadata
+				.exposure
+				.shutter_speed
+				.map(|shutter_speed| shutter_speed.seconds()),
+			"lens": photo.metadata.lens,
+			"focalLength35mm": photo.metadata.exposure.focal_length_35mm(),
+			"exposure": {
+				"aperture": photo.metadata.exposure.aperture,
+				"iso": photo.metadata.exposure.iso,
+				"focalLength": photo.metadata.exposure.focal_length,
+				"focalLength35mm": photo.metadata.exposure.focal_length_35mm,
+			},
+			"exposureCompensation": photo.metadata.exposure_compensation,
+			"meteringMode": photo.metadata.metering_mode,
+			"flash": photo.metadata.flash,
+			"whiteBalance": photo.metadata.white_balance,
+			"createdAt": photo.metadata.created_at.to_rfc3339(),
+			"tags": tags,
+			"hashtags": hashtags,
+			"rating": photo.metadata.rating,
+			"label": photo.metadata.label,
+			"location": location(config.privacy.as_ref(), &photo.metadata)
+		}))
+	}
 }